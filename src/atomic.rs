@@ -2,22 +2,18 @@ use std::default::Default;
 use std::fmt::{Debug, Error as FormatError, Formatter};
 use std::iter::repeat;
 use std::marker::PhantomData;
+use std::mem::{self, size_of};
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::slice;
+use std::sync::atomic::{AtomicPtr, Ordering};
 
 use util::*;
-use {BitSetLike, DrainableBitSet};
+use {BitSetLike, CapacityError, DrainableBitSet};
 
-const SHIFT0: usize = usize::SHIFT0;
-const SHIFT1: usize = usize::SHIFT1;
-const SHIFT2: usize = usize::SHIFT2;
-const SHIFT3: usize = usize::SHIFT3;
-const LOG_BITS: usize = <usize as UnsignedInteger>::LOG_BITS as usize;
-
-/// This is similar to a [`BitSet`] but allows setting of value
+/// This is similar to a [`GenericBitSet`] but allows setting of value
 /// without unique ownership of the structure
 ///
-/// An `AtomicBitSet` has the ability to add an item to the set
+/// A `GenericAtomicBitSet` has the ability to add an item to the set
 /// without unique ownership (given that the set is big enough).
 /// Removing elements does require unique ownership as an effect
 /// of the hierarchy it holds. Worst case multiple writers set the
@@ -28,72 +24,156 @@ const LOG_BITS: usize = <usize as UnsignedInteger>::LOG_BITS as usize;
 /// to know if layer 1-3 would be left in a consistent state if they are
 /// being cleared and set at the same time.
 ///
-/// `AtromicBitSet` resolves this race by disallowing atomic
+/// `GenericAtomicBitSet` resolves this race by disallowing atomic
 /// clearing of bits.
 ///
-/// [`BitSet`]: ../struct.BitSet.html
+/// The set is based on an underlying type `T` which has an atomic
+/// counterpart (`u8`, `u16`, `u32`, `u64` or `usize`), so it can be
+/// combined with a [`GenericBitSet`] of the same `T`.
+///
+/// [`GenericBitSet`]: ../struct.GenericBitSet.html
 #[derive(Debug)]
-pub struct AtomicBitSet {
-    layer3: AtomicUsize,
-    layer2: Vec<AtomicUsize>,
-    layer1: Vec<AtomicBlock>,
+pub struct GenericAtomicBitSet<T: AtomicUnsignedInteger> {
+    layer3: T::Atomic,
+    layer2: Vec<T::Atomic>,
+    layer1: Vec<AtomicBlock<T>>,
+    // The full block summaries of layers 3 and 2, layer 1 keeps its own
+    // in each `AtomicBlock`.
+    full3: T::Atomic,
+    full2: Vec<T::Atomic>,
 }
 
-impl AtomicBitSet {
-    /// Creates an empty `AtomicBitSet`.
-    pub fn new() -> AtomicBitSet {
+/// `GenericAtomicBitSet` of `usize` words.
+pub type AtomicBitSet = GenericAtomicBitSet<usize>;
+
+impl<T: AtomicUnsignedInteger> GenericAtomicBitSet<T> {
+    /// Creates an empty `GenericAtomicBitSet`.
+    pub fn new() -> Self {
         Default::default()
     }
 
-    /// Adds `id` to the `AtomicBitSet`. Returns `true` if the value was
+    /// Adds `id` to the `GenericAtomicBitSet`. Returns `true` if the value was
     /// already in the set.
     ///
     /// Because we cannot safely extend an AtomicBitSet without unique ownership
     /// this will panic if the Index is out of range.
     #[inline]
     pub fn add_atomic(&self, id: Index) -> bool {
-        let (_, p1, p2) = offsets::<usize>(id);
+        let (_, p1, p2) = offsets::<T>(id);
 
         // While it is tempting to check of the bit was set and exit here if it
         // was, this can result in a data race. If this thread and another
         // thread both set the same bit it is possible for the second thread
         // to exit before l3 was set. Resulting in the iterator to be in an
         // incorrect state. The window is small, but it exists.
-        let set = self.layer1[p1].add(id);
-        self.layer2[p2].fetch_or(id.mask(SHIFT2), Ordering::Relaxed);
-        self.layer3.fetch_or(id.mask(SHIFT3), Ordering::Relaxed);
-        set
+        let old = self.layer1[p1].add(id);
+        T::fetch_or(&self.layer2[p2], id.mask(T::SHIFT2), Ordering::Relaxed);
+        T::fetch_or(&self.layer3, id.mask(T::SHIFT3), Ordering::Relaxed);
+        self.check_full(id, old, id.mask(T::SHIFT0));
+        old & id.mask::<T>(T::SHIFT0) != T::ZERO
+    }
+
+    /// Adds `id` to the `GenericAtomicBitSet`. Returns `Ok(true)` if the value was
+    /// already in the set.
+    ///
+    /// Unlike [`add_atomic`](#method.add_atomic), this returns an error
+    /// instead of panicking if the Index is out of range.
+    #[inline]
+    pub fn try_add_atomic(&self, id: Index) -> Result<bool, CapacityError> {
+        if id >= T::MAX_EID {
+            return Err(CapacityError::OutOfRange {
+                index: id,
+                max: T::MAX_EID,
+            });
+        }
+        Ok(self.add_atomic(id))
     }
 
     /// Adds `id` to the `BitSet`. Returns `true` if the value was
     /// already in the set.
     #[inline]
     pub fn add(&mut self, id: Index) -> bool {
-        use std::sync::atomic::Ordering::Relaxed;
-
-        let (_, p1, p2) = offsets::<usize>(id);
-        if self.layer1[p1].add(id) {
+        let (_, p1, p2) = offsets::<T>(id);
+        let old = self.layer1[p1].add(id);
+        if old & id.mask::<T>(T::SHIFT0) != T::ZERO {
             return true;
         }
 
-        self.layer2[p2].store(
-            self.layer2[p2].load(Relaxed) | id.mask::<usize>(SHIFT2),
-            Relaxed,
-        );
-        self.layer3.store(
-            self.layer3.load(Relaxed) | id.mask::<usize>(SHIFT3),
-            Relaxed,
-        );
+        // These are used over a `fetch_or` because we have a mutable
+        // access to the set so this is sound (and faster)
+        *T::get_mut(&mut self.layer2[p2]) |= id.mask(T::SHIFT2);
+        *T::get_mut(&mut self.layer3) |= id.mask(T::SHIFT3);
+        self.check_full(id, old, id.mask(T::SHIFT0));
         false
     }
 
+    /// Adds the bits of `word` to the layer 0 word at offset `p0`,
+    /// setting the summary layers as needed.
+    #[cfg(feature = "serde")]
+    pub(crate) fn insert_word(&mut self, p0: usize, word: T) {
+        if word == T::ZERO {
+            return;
+        }
+        let id = (p0 << T::SHIFT1) as Index;
+        let (_, p1, p2) = offsets::<T>(id);
+        let block = &mut self.layer1[p1];
+        let old = T::fetch_or(
+            &block.atom.get_or_init()[block_index::<T>(id)],
+            word,
+            Ordering::Relaxed,
+        );
+        *T::get_mut(&mut block.mask) |= id.mask(T::SHIFT1);
+        *T::get_mut(&mut self.layer2[p2]) |= id.mask(T::SHIFT2);
+        *T::get_mut(&mut self.layer3) |= id.mask(T::SHIFT3);
+        self.check_full(id, old, word);
+    }
+
+    /// Marks the layer 0 word holding `id` in the full block summaries if
+    /// adding the bits of `added` to its `old` value made it full.
+    ///
+    /// Only the thread which filled a word sees it going from not full to
+    /// full, so each full block is marked once even with concurrent adds.
+    #[inline]
+    fn check_full(&self, id: Index, old: T, added: T) {
+        if old == T::MAX || old | added != T::MAX {
+            return;
+        }
+        let (_, p1, p2) = offsets::<T>(id);
+        let mask = id.mask::<T>(T::SHIFT1);
+        if T::fetch_or(&self.layer1[p1].full, mask, Ordering::Relaxed) | mask != T::MAX {
+            return;
+        }
+        let mask = id.mask::<T>(T::SHIFT2);
+        if T::fetch_or(&self.full2[p2], mask, Ordering::Relaxed) | mask != T::MAX {
+            return;
+        }
+        T::fetch_or(&self.full3, id.mask(T::SHIFT3), Ordering::Relaxed);
+    }
+
+    /// Unmarks the layer 0 word holding `id` from the full block
+    /// summaries, before an index is removed from it.
+    fn unset_full(&mut self, id: Index) {
+        let (_, p1, p2) = offsets::<T>(id);
+        let words = [
+            (T::get_mut(&mut self.layer1[p1].full), T::SHIFT1),
+            (T::get_mut(&mut self.full2[p2]), T::SHIFT2),
+            (T::get_mut(&mut self.full3), T::SHIFT3),
+        ];
+        for (word, shift) in words {
+            let was_full = *word == T::MAX;
+            *word &= !id.mask::<T>(shift);
+            if !was_full {
+                return;
+            }
+        }
+    }
+
     /// Removes `id` from the set, returns `true` if the value
     /// was removed, and `false` if the value was not set
     /// to begin with.
     #[inline]
     pub fn remove(&mut self, id: Index) -> bool {
-        use std::sync::atomic::Ordering::Relaxed;
-        let (_, p1, p2) = offsets::<usize>(id);
+        let (_, p1, p2) = offsets::<T>(id);
 
         // if the bitmask was set we need to clear
         // its bit from layer0 to 3. the layers above only
@@ -102,31 +182,45 @@ impl AtomicBitSet {
         //
         // These are used over a `fetch_and` because we have a mutable
         // access to the AtomicBitSet so this is sound (and faster)
+        if self.layer0(id.offset(T::SHIFT1)) == T::MAX {
+            self.unset_full(id);
+        }
         if !self.layer1[p1].remove(id) {
             return false;
         }
-        if self.layer1[p1].mask.load(Ordering::Relaxed) != 0 {
+        if *T::get_mut(&mut self.layer1[p1].mask) != T::ZERO {
             return true;
         }
 
-        let v = self.layer2[p2].load(Relaxed) & !id.mask::<usize>(SHIFT2);
-        self.layer2[p2].store(v, Relaxed);
-        if v != 0 {
+        let v = T::get_mut(&mut self.layer2[p2]);
+        *v &= !id.mask::<T>(T::SHIFT2);
+        if *v != T::ZERO {
             return true;
         }
 
-        let v = self.layer3.load(Relaxed) & !id.mask::<usize>(SHIFT3);
-        self.layer3.store(v, Relaxed);
-        return true;
+        *T::get_mut(&mut self.layer3) &= !id.mask::<T>(T::SHIFT3);
+        true
     }
 
     /// Returns `true` if `id` is in the set.
     #[inline]
     pub fn contains(&self, id: Index) -> bool {
-        let i = id.offset(SHIFT2);
+        let i = id.offset(T::SHIFT2);
         self.layer1[i].contains(id)
     }
 
+    /// Returns the smallest index in the set, or `None` if the set is empty.
+    #[inline]
+    pub fn first(&self) -> Option<Index> {
+        BitSetLike::first(self)
+    }
+
+    /// Returns the largest index in the set, or `None` if the set is empty.
+    #[inline]
+    pub fn last(&self) -> Option<Index> {
+        BitSetLike::last(self)
+    }
+
     /// Clear all bits in the set
     pub fn clear(&mut self) {
         // This is the same hierarchical-striding used in the iterators.
@@ -134,13 +228,14 @@ impl AtomicBitSet {
         // that are already clear. In the best case when the set is already cleared,
         // this will only touch the highest layer.
 
-        let (mut m3, mut m2) = (self.layer3.swap(0, Ordering::Relaxed), 0usize);
+        let (mut m3, mut m2) = (T::swap(&self.layer3, T::ZERO, Ordering::Relaxed), T::ZERO);
+        *T::get_mut(&mut self.full3) = T::ZERO;
         let mut offset = 0;
 
         loop {
-            if m2 != 0 {
+            if m2 != T::ZERO {
                 let bit = m2.trailing_zeros() as usize;
-                m2 &= !(1 << bit);
+                m2 &= !(T::ONE << T::from_u32(bit as u32));
 
                 // layer 1 & 0 are cleared unconditionally. it's only 32-64 words
                 // and the extra logic to select the correct works is slower
@@ -149,41 +244,76 @@ impl AtomicBitSet {
                 continue;
             }
 
-            if m3 != 0 {
+            if m3 != T::ZERO {
                 let bit = m3.trailing_zeros() as usize;
-                m3 &= !(1 << bit);
-                offset = bit << LOG_BITS;
-                m2 = self.layer2[bit].swap(0, Ordering::Relaxed);
+                m3 &= !(T::ONE << T::from_u32(bit as u32));
+                offset = bit << T::LOG_BITS;
+                m2 = T::swap(&self.layer2[bit], T::ZERO, Ordering::Relaxed);
+                *T::get_mut(&mut self.full2[bit]) = T::ZERO;
                 continue;
             }
             break;
         }
     }
+
+    /// Returns the number of bytes allocated on the heap by the set.
+    ///
+    /// This counts the summary layers, which are allocated up front, and
+    /// the layer 0 blocks, which are allocated on first use.
+    pub fn heap_size(&self) -> usize {
+        let blocks = self
+            .layer1
+            .iter()
+            .filter(|block| block.atom.get().is_some())
+            .count();
+        (self.layer2.capacity() + self.full2.capacity()) * size_of::<T::Atomic>()
+            + self.layer1.capacity() * size_of::<AtomicBlock<T>>()
+            + blocks * T::BITS * size_of::<T::Atomic>()
+    }
+
+    /// Frees the layer 0 blocks which don't hold any index anymore.
+    ///
+    /// Blocks are allocated when an index is first added to them, and
+    /// kept by `remove` and `clear` so adding to them again is cheap.
+    /// This releases the ones left empty.
+    pub fn shrink_to_fit(&mut self) {
+        for block in &mut self.layer1 {
+            if *T::get_mut(&mut block.mask) == T::ZERO {
+                block.atom.free();
+            }
+        }
+    }
 }
 
-impl BitSetLike for AtomicBitSet {
-    type Underlying = usize;
+impl<T: AtomicUnsignedInteger> BitSetLike for GenericAtomicBitSet<T> {
+    type Underlying = T;
 
     #[inline]
-    fn layer3(&self) -> usize {
-        self.layer3.load(Ordering::Relaxed)
-    }
-    #[inline]
-    fn layer2(&self, i: usize) -> usize {
-        self.layer2[i].load(Ordering::Relaxed)
-    }
-    #[inline]
-    fn layer1(&self, i: usize) -> usize {
-        self.layer1[i].mask.load(Ordering::Relaxed)
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        match layer {
+            0 => {
+                let (o1, o0) = (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1));
+                self.layer1[o1]
+                    .atom
+                    .get()
+                    .map(|layer0| T::load(&layer0[o0], Ordering::Relaxed))
+                    .unwrap_or(T::ZERO)
+            }
+            1 => T::load(&self.layer1[idx].mask, Ordering::Relaxed),
+            2 => T::load(&self.layer2[idx], Ordering::Relaxed),
+            3 => T::load(&self.layer3, Ordering::Relaxed),
+            _ => panic!("Invalid layer: {}", layer),
+        }
     }
     #[inline]
-    fn layer0(&self, i: usize) -> usize {
-        let (o1, o0) = (i >> LOG_BITS, i & ((1 << LOG_BITS) - 1));
-        self.layer1[o1]
-            .atom
-            .get()
-            .map(|layer0| layer0[o0].load(Ordering::Relaxed))
-            .unwrap_or(0)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        match layer {
+            0 => self.layer0(idx),
+            1 => T::load(&self.layer1[idx].full, Ordering::Relaxed),
+            2 => T::load(&self.full2[idx], Ordering::Relaxed),
+            3 => T::load(&self.full3, Ordering::Relaxed),
+            _ => panic!("Invalid layer: {}", layer),
+        }
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
@@ -191,46 +321,50 @@ impl BitSetLike for AtomicBitSet {
     }
 }
 
-impl DrainableBitSet for AtomicBitSet {
+impl<T: AtomicUnsignedInteger> DrainableBitSet for GenericAtomicBitSet<T> {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
         self.remove(i)
     }
 }
 
-impl Default for AtomicBitSet {
+impl<T: AtomicUnsignedInteger> Default for GenericAtomicBitSet<T> {
     fn default() -> Self {
-        AtomicBitSet {
-            layer3: Default::default(),
+        GenericAtomicBitSet {
+            layer3: T::new_atomic(T::ZERO),
             layer2: repeat(0)
-                .map(|_| AtomicUsize::new(0))
-                .take(1 << LOG_BITS)
+                .map(|_| T::new_atomic(T::ZERO))
+                .take(1 << T::LOG_BITS)
                 .collect(),
             layer1: repeat(0)
                 .map(|_| AtomicBlock::new())
-                .take(1 << (2 * LOG_BITS))
+                .take(1 << (2 * T::LOG_BITS))
+                .collect(),
+            full3: T::new_atomic(T::ZERO),
+            full2: repeat(0)
+                .map(|_| T::new_atomic(T::ZERO))
+                .take(1 << T::LOG_BITS)
                 .collect(),
         }
     }
 }
 
-struct OnceAtom {
-    inner: AtomicPtr<[AtomicUsize; 1 << LOG_BITS]>,
-    marker: PhantomData<Option<Box<[AtomicUsize; 1 << LOG_BITS]>>>,
+/// A lazily allocated block of `T::BITS` atomics.
+///
+/// The pointer is the start of a boxed slice of length `T::BITS`, stored
+/// thin since `AtomicPtr` can't hold a slice pointer.
+struct OnceAtom<T: AtomicUnsignedInteger> {
+    inner: AtomicPtr<T::Atomic>,
+    marker: PhantomData<Option<Box<[T::Atomic]>>>,
 }
 
-impl Drop for OnceAtom {
+impl<T: AtomicUnsignedInteger> Drop for OnceAtom<T> {
     fn drop(&mut self) {
-        let ptr = *self.inner.get_mut();
-        if !ptr.is_null() {
-            // SAFETY: If the pointer is not null, we created it from
-            // `Box::into_raw` in `Self::atom_get_or_init`.
-            drop(unsafe { Box::from_raw(ptr) });
-        }
+        self.free();
     }
 }
 
-impl OnceAtom {
+impl<T: AtomicUnsignedInteger> OnceAtom<T> {
     fn new() -> Self {
         Self {
             inner: AtomicPtr::new(ptr::null_mut()),
@@ -238,11 +372,22 @@ impl OnceAtom {
         }
     }
 
-    fn get_or_init(&self) -> &[AtomicUsize; 1 << LOG_BITS] {
+    /// Drops the block, if it was allocated.
+    fn free(&mut self) {
+        let ptr = mem::replace(self.inner.get_mut(), ptr::null_mut());
+        if !ptr.is_null() {
+            // SAFETY: If the pointer is not null, we created it from
+            // `Box::into_raw` of a slice of `T::BITS` atomics in
+            // `Self::get_or_init`.
+            drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, T::BITS)) });
+        }
+    }
+
+    fn get_or_init(&self) -> &[T::Atomic] {
         let current_ptr = self.inner.load(Ordering::Acquire);
         let ptr = if current_ptr.is_null() {
-            const ZERO: AtomicUsize = AtomicUsize::new(0);
-            let new_ptr = Box::into_raw(Box::new([ZERO; 1 << LOG_BITS]));
+            let new_box: Box<[T::Atomic]> = (0..T::BITS).map(|_| T::new_atomic(T::ZERO)).collect();
+            let new_ptr = Box::into_raw(new_box) as *mut T::Atomic;
             if let Err(existing_ptr) = self.inner.compare_exchange(
                 ptr::null_mut(),
                 new_ptr,
@@ -253,7 +398,7 @@ impl OnceAtom {
             ) {
                 // SAFETY: We obtained this pointer from `Box::into_raw` above
                 // and failed to publish it to the `AtomicPtr`.
-                drop(unsafe { Box::from_raw(new_ptr) });
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(new_ptr, T::BITS)) });
                 existing_ptr
             } else {
                 new_ptr
@@ -264,67 +409,79 @@ impl OnceAtom {
 
         // SAFETY: We checked that this pointer is not null (either by
         // `.is_null()` check, `compare_exhange`, or from `Box::into_raw`). We
-        // created from `Box::into_raw` (at some point) and we only use it to
-        // create immutable references (unless we have exclusive access to self)
-        unsafe { &*ptr }
+        // created from `Box::into_raw` of `T::BITS` atomics (at some point) and
+        // we only use it to create immutable references (unless we have
+        // exclusive access to self)
+        unsafe { slice::from_raw_parts(ptr, T::BITS) }
     }
 
-    fn get(&self) -> Option<&[AtomicUsize; 1 << LOG_BITS]> {
+    fn get(&self) -> Option<&[T::Atomic]> {
         let ptr = self.inner.load(Ordering::Acquire);
-        // SAFETY: If it is not null, we created this pointer from
-        // `Box::into_raw` and only use it to create immutable references
-        // (unless we have exclusive access to self)
-        unsafe { ptr.as_ref() }
+        if ptr.is_null() {
+            return None;
+        }
+        // SAFETY: It is not null, so we created this pointer from
+        // `Box::into_raw` of `T::BITS` atomics and only use it to create
+        // immutable references (unless we have exclusive access to self)
+        Some(unsafe { slice::from_raw_parts(ptr, T::BITS) })
     }
 
-    fn get_mut(&mut self) -> Option<&mut [AtomicUsize; 1 << LOG_BITS]> {
-        let ptr = self.inner.get_mut();
-        // SAFETY: If this is not null, we created this pointer from
-        // `Box::into_raw` and we have an exclusive borrow of self.
-        unsafe { ptr.as_mut() }
+    fn get_mut(&mut self) -> Option<&mut [T::Atomic]> {
+        let ptr = *self.inner.get_mut();
+        if ptr.is_null() {
+            return None;
+        }
+        // SAFETY: It is not null, so we created this pointer from
+        // `Box::into_raw` of `T::BITS` atomics and we have an exclusive
+        // borrow of self.
+        Some(unsafe { slice::from_raw_parts_mut(ptr, T::BITS) })
     }
 }
 
-struct AtomicBlock {
-    mask: AtomicUsize,
-    atom: OnceAtom,
+struct AtomicBlock<T: AtomicUnsignedInteger> {
+    mask: T::Atomic,
+    /// The full block summary of `mask`.
+    full: T::Atomic,
+    atom: OnceAtom<T>,
 }
 
-impl AtomicBlock {
-    fn new() -> AtomicBlock {
+impl<T: AtomicUnsignedInteger> AtomicBlock<T> {
+    fn new() -> AtomicBlock<T> {
         AtomicBlock {
-            mask: AtomicUsize::new(0),
+            mask: T::new_atomic(T::ZERO),
+            full: T::new_atomic(T::ZERO),
             atom: OnceAtom::new(),
         }
     }
 
-    fn add(&self, id: Index) -> bool {
-        let (i, m) = (id.row::<usize>(SHIFT1), id.mask::<usize>(SHIFT0));
-        let old = self.atom.get_or_init()[i].fetch_or(m, Ordering::Relaxed);
-        self.mask.fetch_or(id.mask(SHIFT1), Ordering::Relaxed);
-        old & m != 0
+    /// Adds `id` to the block, returning the previous value of its word.
+    fn add(&self, id: Index) -> T {
+        let (i, m) = (block_index::<T>(id), id.mask::<T>(T::SHIFT0));
+        let old = T::fetch_or(&self.atom.get_or_init()[i], m, Ordering::Relaxed);
+        T::fetch_or(&self.mask, id.mask(T::SHIFT1), Ordering::Relaxed);
+        old
     }
 
     fn contains(&self, id: Index) -> bool {
         self.atom
             .get()
             .map(|layer0| {
-                layer0[id.row::<usize>(SHIFT1)].load(Ordering::Relaxed) & id.mask::<usize>(SHIFT0)
-                    != 0
+                T::load(&layer0[block_index::<T>(id)], Ordering::Relaxed) & id.mask::<T>(T::SHIFT0)
+                    != T::ZERO
             })
             .unwrap_or(false)
     }
 
     fn remove(&mut self, id: Index) -> bool {
         if let Some(layer0) = self.atom.get_mut() {
-            let (i, m) = (id.row::<usize>(SHIFT1), !id.mask::<usize>(SHIFT0));
-            let v = layer0[i].get_mut();
-            let was_set = *v & id.mask::<usize>(SHIFT0) == id.mask(SHIFT0);
-            *v = *v & m;
-            if *v == 0 {
+            let (i, m) = (block_index::<T>(id), id.mask::<T>(T::SHIFT0));
+            let v = T::get_mut(&mut layer0[i]);
+            let was_set = *v & m == m;
+            *v &= !m;
+            if *v == T::ZERO {
                 // no other bits are set
                 // so unset bit in the next level up
-                *self.mask.get_mut() &= !id.mask::<usize>(SHIFT1);
+                *T::get_mut(&mut self.mask) &= !id.mask::<T>(T::SHIFT1);
             }
             was_set
         } else {
@@ -333,31 +490,48 @@ impl AtomicBlock {
     }
 
     fn clear(&mut self) {
-        *self.mask.get_mut() = 0;
-        self.atom.get_mut().map(|layer0| {
+        *T::get_mut(&mut self.mask) = T::ZERO;
+        *T::get_mut(&mut self.full) = T::ZERO;
+        if let Some(layer0) = self.atom.get_mut() {
             for l in layer0 {
-                *l.get_mut() = 0;
+                *T::get_mut(l) = T::ZERO;
             }
-        });
+        }
     }
 }
 
-impl Debug for AtomicBlock {
+impl<T: AtomicUnsignedInteger> Debug for AtomicBlock<T> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
         f.debug_struct("AtomicBlock")
             .field("mask", &self.mask)
-            .field("atom", &self.atom.get().unwrap().iter())
+            .field("full", &self.full)
+            .field("atom", &self.atom.get())
             .finish()
     }
 }
 
 #[cfg(test)]
 mod atomic_set_test {
-    use {AtomicBitSet, BitSetAnd, BitSetLike};
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use std::mem::size_of;
 
-    #[test]
-    fn insert() {
-        let mut c = AtomicBitSet::new();
+    use util::AtomicUnsignedInteger;
+    use {BitSetAnd, BitSetLike, BitSetNot, GenericAtomicBitSet, GenericBitSet};
+
+    #[test_with(u32, u64, usize)]
+    fn debug<T: AtomicUnsignedInteger>() {
+        let mut set = GenericAtomicBitSet::<T>::new();
+        // The blocks are only allocated once an index is added to them.
+        assert!(format!("{:?}", set).contains("atom: None"));
+        set.add(3);
+        assert!(format!("{:?}", set).contains("atom: Some(["));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn insert<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
         for i in 0..1_000 {
             assert!(!c.add(i));
             assert!(c.add(i));
@@ -368,9 +542,9 @@ mod atomic_set_test {
         }
     }
 
-    #[test]
-    fn insert_100k() {
-        let mut c = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn insert_100k<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
         for i in 0..100_000 {
             assert!(!c.add(i));
             assert!(c.add(i));
@@ -381,9 +555,9 @@ mod atomic_set_test {
         }
     }
 
-    #[test]
-    fn add_atomic() {
-        let c = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn add_atomic<T: AtomicUnsignedInteger>() {
+        let c = GenericAtomicBitSet::<T>::new();
         for i in 0..1_000 {
             assert!(!c.add_atomic(i));
             assert!(c.add_atomic(i));
@@ -394,9 +568,9 @@ mod atomic_set_test {
         }
     }
 
-    #[test]
-    fn add_atomic_100k() {
-        let c = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn add_atomic_100k<T: AtomicUnsignedInteger>() {
+        let c = GenericAtomicBitSet::<T>::new();
         for i in 0..100_000 {
             assert!(!c.add_atomic(i));
             assert!(c.add_atomic(i));
@@ -407,9 +581,18 @@ mod atomic_set_test {
         }
     }
 
-    #[test]
-    fn remove() {
-        let mut c = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn try_add_atomic<T: AtomicUnsignedInteger>() {
+        let c = GenericAtomicBitSet::<T>::new();
+        assert_eq!(c.try_add_atomic(1_000), Ok(false));
+        assert_eq!(c.try_add_atomic(1_000), Ok(true));
+        assert!(c.try_add_atomic(u32::MAX).is_err());
+        assert!(c.contains(1_000));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn remove<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
         for i in 0..1_000 {
             assert!(!c.add(i));
         }
@@ -422,9 +605,9 @@ mod atomic_set_test {
         }
     }
 
-    #[test]
-    fn iter() {
-        let mut c = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn iter<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
         for i in 0..100_000 {
             c.add(i);
         }
@@ -437,10 +620,10 @@ mod atomic_set_test {
         assert_eq!(count, 100_000);
     }
 
-    #[test]
-    fn iter_odd_even() {
-        let mut odd = AtomicBitSet::new();
-        let mut even = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn iter_odd_even<T: AtomicUnsignedInteger>() {
+        let mut odd = GenericAtomicBitSet::<T>::new();
+        let mut even = GenericAtomicBitSet::<T>::new();
         for i in 0..100_000 {
             if i % 2 == 1 {
                 odd.add(i);
@@ -454,9 +637,68 @@ mod atomic_set_test {
         assert_eq!(BitSetAnd(&odd, &even).iter().count(), 0);
     }
 
-    #[test]
-    fn clear() {
-        let mut set = AtomicBitSet::new();
+    #[test_with(u32, u64, usize)]
+    fn next_prev_set<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        assert_eq!(c.next_set(0), None);
+        assert_eq!(c.prev_set(u32::MAX), None);
+
+        for &i in &[5, 64, 70_000, 1_048_575] {
+            c.add(i);
+        }
+        assert_eq!(c.next_set(0), Some(5));
+        assert_eq!(c.next_set(6), Some(64));
+        assert_eq!(c.next_set(65), Some(70_000));
+        assert_eq!(c.next_set(70_001), Some(1_048_575));
+        assert_eq!(c.prev_set(u32::MAX), Some(1_048_575));
+        assert_eq!(c.prev_set(1_048_574), Some(70_000));
+        assert_eq!(c.prev_set(4), None);
+
+        c.remove(1_048_575);
+        assert_eq!(c.next_set(70_001), None);
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn first_last<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        assert_eq!(c.first(), None);
+        assert_eq!(c.last(), None);
+
+        for &i in &[70_000, 5, 1_048_575, 64] {
+            c.add(i);
+        }
+        assert_eq!(c.first(), Some(5));
+        assert_eq!(c.last(), Some(1_048_575));
+
+        c.remove(5);
+        c.remove(1_048_575);
+        assert_eq!(c.first(), Some(64));
+        assert_eq!(c.last(), Some(70_000));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn subset_disjoint<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        let mut d = GenericAtomicBitSet::<T>::new();
+        for i in 0..1_000 {
+            c.add(i * 2);
+            if i % 2 == 0 {
+                d.add(i * 2);
+            }
+        }
+        assert!(d.is_subset(&c));
+        assert!(c.is_superset(&d));
+        assert!(!c.is_subset(&d));
+        assert!(c.intersects(&d));
+
+        let e: GenericAtomicBitSet<T> = (5_000..6_000).collect();
+        assert!(c.is_disjoint(&e));
+        assert!(BitSetAnd(&c, &d).is_disjoint(&e));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn clear<T: AtomicUnsignedInteger>() {
+        let mut set = GenericAtomicBitSet::<T>::new();
         for i in 0..1_000 {
             set.add(i);
         }
@@ -499,4 +741,85 @@ mod atomic_set_test {
         set.clear();
         assert_eq!((&set).iter().count(), 0);
     }
+
+    #[test_with(u32, u64, usize)]
+    fn shrink_to_fit<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        let empty = c.heap_size();
+        let block = T::BITS * size_of::<T::Atomic>();
+        let step = (T::BITS * T::BITS) as u32;
+        for i in 0..10 {
+            c.add_atomic(i * step);
+        }
+        assert_eq!(c.heap_size(), empty + 10 * block);
+
+        for i in 0..5 {
+            c.remove(i * step);
+        }
+        assert_eq!(c.heap_size(), empty + 10 * block);
+        c.shrink_to_fit();
+        assert_eq!(c.heap_size(), empty + 5 * block);
+        assert_eq!((&c).iter().count(), 5);
+
+        c.clear();
+        c.shrink_to_fit();
+        assert_eq!(c.heap_size(), empty);
+        assert!(!c.add(step));
+        assert_eq!(c.first(), Some(step));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn combine_with_bitset<T: AtomicUnsignedInteger>() {
+        let atomic: GenericAtomicBitSet<T> = (0..1_000).filter(|i| i % 2 == 0).collect();
+        let set: GenericBitSet<T> = (0..1_000).filter(|i| i % 3 == 0).collect();
+        assert_eq!(
+            (&atomic & &set).iter().collect::<Vec<_>>(),
+            (0..1_000).step_by(6).collect::<Vec<_>>()
+        );
+        assert_eq!((&atomic | &set).iter().count(), 667);
+        assert!(atomic.intersects(&set));
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn not_skips_full_blocks<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        let block = (T::BITS * T::BITS) as u32;
+        for i in 0..2 * block {
+            c.add_atomic(i);
+        }
+        c.add(2 * block + 1);
+        assert_eq!(c.get_full_from_layer(1, 0), T::MAX);
+        assert_eq!(c.get_full_from_layer(2, 0), T::ONE | T::ONE << T::ONE);
+        assert_eq!(BitSetNot(&c).get_from_layer(1, 1), T::ZERO);
+        assert_eq!(BitSetNot(&c).first(), Some(2 * block));
+
+        assert!(c.remove(block + 3));
+        assert_eq!(c.get_full_from_layer(2, 0), T::ONE);
+        assert_eq!(c.get_full_from_layer(1, 1), !T::ONE);
+        assert_eq!(BitSetNot(&c).first(), Some(block + 3));
+        assert!(!c.add(block + 3));
+        assert_eq!(c.get_full_from_layer(2, 0), T::ONE | T::ONE << T::ONE);
+
+        c.clear();
+        assert_eq!(c.get_full_from_layer(1, 0), T::ZERO);
+        assert_eq!(c.get_full_from_layer(2, 0), T::ZERO);
+        assert_eq!(BitSetNot(&c).first(), Some(0));
+    }
+
+    #[test_with(u8, u16)]
+    fn small_words<T: AtomicUnsignedInteger>() {
+        let mut c = GenericAtomicBitSet::<T>::new();
+        let max = T::MAX_EID - 1;
+        for &i in &[0, 9, 255, max] {
+            assert!(!c.add_atomic(i));
+            assert!(c.add_atomic(i));
+        }
+        assert!(c.try_add_atomic(max + 1).is_err());
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [0, 9, 255, max]);
+        assert!(c.remove(9));
+        assert!(!c.contains(9));
+        assert_eq!(c.last(), Some(max));
+        c.clear();
+        assert_eq!(c.first(), None);
+    }
 }
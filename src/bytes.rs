@@ -0,0 +1,434 @@
+//! Compact binary encoding of `GenericBitSet`, whatever its storage.
+//!
+//! An encoding starts with three bytes, the version of the format, the
+//! number of bytes per word and the number of layers. The words of the
+//! layers follow in depth first order: the top layer word, then for each of
+//! its set bits the word below it, and so on down to layer 0. Only the words
+//! below set summary bits are written, each in little endian byte order, and
+//! none of them is zero.
+//!
+//! Sets of `usize` are written with `u64` words whatever the platform, so an
+//! encoding written on a 64 bit platform can be read on a 32 bit one.
+//!
+//! The position of a word depends on all of the words before it, so an
+//! encoding can only be read as a whole. Memory mapping it, or reading part
+//! of it without decoding the rest, is not a goal of the format.
+
+use iter::WordIter;
+use storage::Storage;
+use util::*;
+use {BitSetLike, DecodeError, GenericBitSet};
+
+const VERSION: u8 = 1;
+const WIDE_BITS: usize = <u64 as UnsignedInteger>::BITS;
+
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> GenericBitSet<T, L, S> {
+    /// Encodes the set into a compact byte format, which doesn't depend on the
+    /// endianness of the platform and can be read back with [`from_bytes`].
+    ///
+    /// Only the non-zero words are written, so a set holding a few indices
+    /// encodes to a few bytes no matter how large those indices are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let mut set = BitSet::new();
+    /// set.add(1_000_000);
+    ///
+    /// let bytes = set.to_bytes();
+    /// assert!(bytes.len() < 64);
+    /// assert_eq!(BitSet::from_bytes(&bytes), Ok(set));
+    /// ```
+    ///
+    /// [`from_bytes`]: #method.from_bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if T::ENCODED_BITS != T::BITS {
+            return self.to_wide_bytes();
+        }
+        let mut bytes = vec![VERSION, (T::BITS / 8) as u8, L as u8];
+        self.encode(L - 1, 0, &mut bytes);
+        bytes
+    }
+
+    /// Writes the word at layer and index followed by the words below it,
+    /// skipping the summary bits over empty words, and returns whether the
+    /// word was written.
+    ///
+    /// The summary layers of the set are kept exact, but they are still
+    /// rebuilt from the words below them, so an encoding never holds a zero
+    /// word. The top word is always written.
+    fn encode(&self, level: usize, idx: usize, bytes: &mut Vec<u8>) -> bool {
+        let width = T::BITS / 8;
+        let start = bytes.len();
+        let mut word = self.get_from_layer(level, idx);
+        bytes.resize(start + width, 0);
+        if level > 0 {
+            let mut bits = word;
+            while bits != T::ZERO {
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - T::ONE;
+                if !self.encode(level - 1, (idx << T::LOG_BITS) | bit, bytes) {
+                    word &= !(T::ONE << T::from_u32(bit as u32));
+                }
+            }
+        }
+        if word == T::ZERO && level < L - 1 {
+            bytes.truncate(start);
+            return false;
+        }
+        for (i, byte) in bytes[start..start + width].iter_mut().enumerate() {
+            *byte = (word >> T::from_u32(8 * i as u32)).to_u32() as u8;
+        }
+        true
+    }
+
+    /// Decodes a set from the format written by [`to_bytes`].
+    ///
+    /// The input isn't trusted: an error is returned if it was written by
+    /// another version of the format or for another word size or number
+    /// of layers, if it is
+    /// truncated or followed by more bytes, if a summary bit is set over
+    /// an empty word, or if it holds indices past the capacity of the set.
+    ///
+    /// [`to_bytes`]: #method.to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match *bytes {
+            [VERSION, width, layers, ref words @ ..] => {
+                let width = width as usize * 8;
+                let wide = width != T::BITS && width == T::ENCODED_BITS;
+                if width != T::BITS && !wide {
+                    return Err(DecodeError::WordSizeMismatch {
+                        expected: T::ENCODED_BITS,
+                        found: width,
+                    });
+                }
+                if layers as usize != L {
+                    return Err(DecodeError::LayersMismatch {
+                        expected: L,
+                        found: layers as usize,
+                    });
+                }
+                let mut words = words;
+                let set = if wide {
+                    Self::decode_wide(&mut words)?
+                } else {
+                    let mut set = Self::default();
+                    decode::<T, _, L>(L - 1, 0, &mut words, &mut |p0, word| {
+                        set.insert_word(p0, word);
+                        Ok(())
+                    })?;
+                    set
+                };
+                if !words.is_empty() {
+                    return Err(DecodeError::TrailingBytes);
+                }
+                Ok(set)
+            }
+            [version, _, _, ..] => Err(DecodeError::UnsupportedVersion(version)),
+            _ => Err(DecodeError::UnexpectedEnd),
+        }
+    }
+
+    /// Decodes the words written by [`to_wide_bytes`], splitting them into
+    /// the narrower words of the set.
+    ///
+    /// [`to_wide_bytes`]: #method.to_wide_bytes
+    fn decode_wide(bytes: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut set = Self::default();
+        let max = (capacity::<T>(L) >> T::LOG_BITS) as usize;
+        let parts = WIDE_BITS / T::BITS;
+        decode::<u64, _, L>(L - 1, 0, bytes, &mut |p0, word| {
+            for i in 0..parts {
+                let part = T::from_u64(word >> (i * T::BITS));
+                let idx = p0 * parts + i;
+                if part != T::ZERO && idx >= max {
+                    return Err(DecodeError::OutOfRange);
+                }
+                set.insert_word(idx, part);
+            }
+            Ok(())
+        })?;
+        Ok(set)
+    }
+
+    /// Encodes the set with the `u64` words holding the same indices, which
+    /// are wider than the words of the set.
+    fn to_wide_bytes(&self) -> Vec<u8> {
+        let mut words: Vec<(usize, u64)> = Vec::new();
+        for (p0, word) in WordIter::<_, L>::new(self) {
+            let bit = p0 << T::LOG_BITS;
+            let (idx, word) = (bit / WIDE_BITS, word.to_u64() << (bit % WIDE_BITS));
+            match words.last_mut() {
+                Some(&mut (last, ref mut wide)) if last == idx => *wide |= word,
+                _ => words.push((idx, word)),
+            }
+        }
+        let mut bytes = vec![VERSION, (WIDE_BITS / 8) as u8, L as u8];
+        encode_wide(L - 1, &words, &mut bytes);
+        bytes
+    }
+}
+
+/// Writes the `u64` word at `level` over the non-zero layer 0 `words`, sorted
+/// by offset, followed by the words below it.
+fn encode_wide(level: usize, words: &[(usize, u64)], bytes: &mut Vec<u8>) {
+    let log_bits = <u64 as UnsignedInteger>::LOG_BITS;
+    if level == 0 {
+        let word = words.first().map_or(0, |&(_, word)| word);
+        bytes.extend_from_slice(&word.to_le_bytes());
+        return;
+    }
+    // The offset of the word one layer down holding each word of layer 0.
+    let shift = (log_bits * (level - 1)) as u32;
+    let child = |&(p0, _): &(usize, u64)| p0.checked_shr(shift).unwrap_or(0);
+    let word = words
+        .iter()
+        .fold(0u64, |word, p0| word | 1 << (child(p0) & (WIDE_BITS - 1)));
+    bytes.extend_from_slice(&word.to_le_bytes());
+    let mut words = words;
+    while let Some(first) = words.first() {
+        let len = words
+            .iter()
+            .take_while(|p0| child(p0) == child(first))
+            .count();
+        encode_wide(level - 1, &words[..len], bytes);
+        words = &words[len..];
+    }
+}
+
+/// Reads the word at layer and index followed by the words below it,
+/// passing the layer 0 words to `insert`.
+fn decode<W, F, const L: usize>(
+    level: usize,
+    idx: usize,
+    bytes: &mut &[u8],
+    insert: &mut F,
+) -> Result<(), DecodeError>
+where
+    W: UnsignedInteger,
+    F: FnMut(usize, W) -> Result<(), DecodeError>,
+{
+    let width = W::BITS / 8;
+    if bytes.len() < width {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (word, rest) = bytes.split_at(width);
+    *bytes = rest;
+    let word = word.iter().enumerate().fold(W::ZERO, |word, (i, &byte)| {
+        word | (W::from_u32(byte as u32) << W::from_u32(8 * i as u32))
+    });
+    if level == L - 1 && word & !top_mask::<W>(L) != W::ZERO {
+        return Err(DecodeError::OutOfRange);
+    }
+    if word == W::ZERO && level < L - 1 {
+        return Err(DecodeError::InconsistentSummary {
+            layer: level,
+            offset: idx,
+        });
+    }
+    if level == 0 {
+        insert(idx, word)?;
+    } else {
+        let mut bits = word;
+        while bits != W::ZERO {
+            let bit = bits.trailing_zeros() as usize;
+            bits &= bits - W::ONE;
+            decode::<W, F, L>(level - 1, (idx << W::LOG_BITS) | bit, bytes, insert)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use util::{capacity, top_mask};
+    use {BitSetLike, DecodeError, GenericBitSet, Index, UnsignedInteger};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn round_trip<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&set.to_bytes()),
+            Ok(set.clone())
+        );
+        for i in (0..10_000).filter(|i| i % 7 == 0).chain(500_000..500_100) {
+            set.add(i);
+        }
+        let read = GenericBitSet::<T>::from_bytes(&set.to_bytes()).unwrap();
+        assert_eq!(read.len(), set.len());
+        assert_eq!(
+            (&read).iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn round_trip_stale_summaries<T: UnsignedInteger>() {
+        let mut set: GenericBitSet<T> = [1, 64, 100_000].iter().cloned().collect();
+        set &= &[2, 65, 100_000]
+            .iter()
+            .cloned()
+            .collect::<GenericBitSet<T>>();
+        let read = GenericBitSet::<T>::from_bytes(&set.to_bytes()).unwrap();
+        assert!((&read).iter().eq((&set).iter()));
+
+        set.remove(100_000);
+        let read = GenericBitSet::<T>::from_bytes(&set.to_bytes()).unwrap();
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn round_trip_stale_summaries_small_words() {
+        let mut set: GenericBitSet<u8, 6> = [1, 64, 20_000].iter().cloned().collect();
+        set &= &[2, 65].iter().cloned().collect::<GenericBitSet<u8, 6>>();
+        assert!(set.is_empty());
+        let read = GenericBitSet::<u8, 6>::from_bytes(&set.to_bytes()).unwrap();
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn out_of_range() {
+        fn check<T: UnsignedInteger, const L: usize>() {
+            let mut set = GenericBitSet::<T, L>::new();
+            set.add(Index::MAX);
+            let mut bytes = set.to_bytes();
+            assert_eq!(GenericBitSet::<T, L>::from_bytes(&bytes), Ok(set));
+            // Set the bit following the highest valid bit of the top word.
+            let top = T::BITS - (!top_mask::<T>(L)).trailing_zeros() as usize;
+            bytes[3 + top / 8] |= 1 << (top % 8);
+            assert_eq!(
+                GenericBitSet::<T, L>::from_bytes(&bytes),
+                Err(DecodeError::OutOfRange)
+            );
+        }
+        check::<u64, 6>();
+        check::<u128, 5>();
+        check::<u16, 9>();
+        check::<u8, 11>();
+    }
+
+    #[test]
+    fn usize_as_u64() {
+        let indices = [0, 63, 64, 1_000, 100_000, (1 << 20) - 1];
+        let set: GenericBitSet<usize> = indices.iter().cloned().collect();
+        let bytes = set.to_bytes();
+        assert_eq!(bytes[1], 8);
+        let wide: GenericBitSet<u64> = indices.iter().cloned().collect();
+        assert_eq!(bytes, wide.to_bytes());
+        assert_eq!(GenericBitSet::from_bytes(&bytes), Ok(set));
+    }
+
+    #[test_with(u8, u16, u32)]
+    fn narrow_from_wide<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T, 6>::new();
+        set.insert_range(10..1_000);
+        set.add(T::MAX_EID - 1);
+        let bytes = set.to_wide_bytes();
+        assert_eq!(&bytes[..3], [1, 8, 6]);
+        let wide = GenericBitSet::<u64, 6>::from_bytes(&bytes).unwrap();
+        assert!((&wide).iter().eq((&set).iter()));
+        assert_eq!(GenericBitSet::decode_wide(&mut &bytes[3..]), Ok(set));
+
+        let mut wide = GenericBitSet::<u64, 6>::new();
+        wide.add(capacity::<T>(6) as Index);
+        assert_eq!(
+            GenericBitSet::<T, 6>::decode_wide(&mut &wide.to_bytes()[3..]),
+            Err(DecodeError::OutOfRange)
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn compact<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        set.add(T::MAX_EID - 1);
+        let width = T::BITS / 8;
+        let bytes = set.to_bytes();
+        assert_eq!(bytes.len(), 3 + 4 * width);
+        // Every word has only its highest bit set.
+        for word in bytes[3..].chunks(width) {
+            assert_eq!(word[width - 1], 0x80);
+            assert!(word[..width - 1].iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    fn stable_encoding() {
+        let mut set = GenericBitSet::<u32>::new();
+        set.add(1);
+        set.add(33);
+        assert_eq!(
+            set.to_bytes(),
+            vec![1, 4, 4, 1, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0]
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn invalid<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        set.add(12_345);
+        let bytes = set.to_bytes();
+        let width = T::BITS / 8;
+
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&[]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        for end in 0..bytes.len() {
+            assert_eq!(
+                GenericBitSet::<T>::from_bytes(&bytes[..end]),
+                Err(DecodeError::UnexpectedEnd)
+            );
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+
+        let mut version = bytes.clone();
+        version[0] = 2;
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&version),
+            Err(DecodeError::UnsupportedVersion(2))
+        );
+
+        let mut size = bytes.clone();
+        size[1] = 2;
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&size),
+            Err(DecodeError::WordSizeMismatch {
+                expected: T::BITS,
+                found: 16
+            })
+        );
+
+        let mut layers = bytes.clone();
+        layers[2] = 5;
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&layers),
+            Err(DecodeError::LayersMismatch {
+                expected: 4,
+                found: 5
+            })
+        );
+
+        // Clear the layer 0 word while keeping its summary bits.
+        let mut empty = bytes.clone();
+        for byte in &mut empty[3 + 3 * width..] {
+            *byte = 0;
+        }
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&empty),
+            Err(DecodeError::InconsistentSummary {
+                layer: 0,
+                offset: 12_345 >> T::LOG_BITS
+            })
+        );
+    }
+}
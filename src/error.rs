@@ -0,0 +1,117 @@
+use std::collections::TryReserveError;
+use std::error::Error;
+use std::fmt::{Display, Error as FormatError, Formatter};
+
+use util::Index;
+
+/// The error returned by the fallible methods of the bit sets, such as
+/// [`GenericBitSet::try_add`], when an index can't be stored.
+///
+/// [`GenericBitSet::try_add`]: struct.GenericBitSet.html#method.try_add
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CapacityError {
+    /// The index is larger than what the set can hold.
+    OutOfRange {
+        /// The index that was rejected.
+        index: Index,
+        /// The exclusive upper bound of the indices the set can hold.
+        max: Index,
+    },
+    /// The memory for the layers of the set couldn't be allocated.
+    AllocationFailed(TryReserveError),
+}
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            CapacityError::OutOfRange { index, max } => {
+                write!(f, "Expected index to be less then {}, found {}", max, index)
+            }
+            CapacityError::AllocationFailed(ref err) => {
+                write!(f, "Failed to allocate the bit set: {}", err)
+            }
+        }
+    }
+}
+
+impl Error for CapacityError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CapacityError::OutOfRange { .. } => None,
+            CapacityError::AllocationFailed(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<TryReserveError> for CapacityError {
+    fn from(err: TryReserveError) -> Self {
+        CapacityError::AllocationFailed(err)
+    }
+}
+
+/// The error returned by [`GenericBitSet::from_bytes`] when the bytes
+/// aren't a valid encoding of a bit set.
+///
+/// [`GenericBitSet::from_bytes`]: struct.GenericBitSet.html#method.from_bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The encoding was written by an unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The encoding was written for a bit set with a different word size.
+    WordSizeMismatch {
+        /// The number of bits per word of the decoded bit set.
+        expected: usize,
+        /// The number of bits per word of the encoding.
+        found: usize,
+    },
+    /// The encoding was written for a bit set with a different number of layers.
+    LayersMismatch {
+        /// The number of layers of the decoded bit set.
+        expected: usize,
+        /// The number of layers of the encoding.
+        found: usize,
+    },
+    /// The bytes ended in the middle of the encoding.
+    UnexpectedEnd,
+    /// There are bytes left after the end of the encoding.
+    TrailingBytes,
+    /// The top layer word has bits set for indices past the capacity of the set.
+    OutOfRange,
+    /// A summary bit is set but the word below it is empty.
+    InconsistentSummary {
+        /// The layer of the empty word.
+        layer: usize,
+        /// The offset of the empty word within its layer.
+        offset: usize,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encoding version {}", version)
+            }
+            DecodeError::WordSizeMismatch { expected, found } => write!(
+                f,
+                "Expected words of {} bits, found {} bits",
+                expected, found
+            ),
+            DecodeError::LayersMismatch { expected, found } => {
+                write!(f, "Expected {} layers, found {} layers", expected, found)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of the encoding"),
+            DecodeError::TrailingBytes => write!(f, "Unexpected bytes after the encoding"),
+            DecodeError::OutOfRange => {
+                write!(f, "The encoding holds indices past the capacity of the set")
+            }
+            DecodeError::InconsistentSummary { layer, offset } => write!(
+                f,
+                "Word {} of layer {} is empty but its summary bit is set",
+                offset, layer
+            ),
+        }
+    }
+}
+
+impl Error for DecodeError {}
@@ -0,0 +1,383 @@
+use std::fmt::{Debug, Error as FormatError, Formatter};
+use std::ops::{BitAnd, BitOr, BitXor, Not, Sub};
+
+use util::*;
+use {BitIter, BitSetLike};
+
+/// An object safe view of a [`BitSetLike`], so sets of different types can
+/// be stored and combined behind a `&dyn DynBitSetLike`.
+///
+/// It is implemented for every `BitSetLike`, and `dyn DynBitSetLike`
+/// implements `BitSetLike` in turn. The methods are prefixed with `dyn_`,
+/// so they don't clash with the ones of `BitSetLike` when both traits are
+/// in scope.
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+pub trait DynBitSetLike<T: UnsignedInteger, const L: usize = LAYERS> {
+    /// See [`BitSetLike::get_from_layer`](trait.BitSetLike.html#method.get_from_layer).
+    fn dyn_get_from_layer(&self, layer: usize, idx: usize) -> T;
+
+    /// See [`BitSetLike::get_full_from_layer`](trait.BitSetLike.html#method.get_full_from_layer).
+    fn dyn_get_full_from_layer(&self, layer: usize, idx: usize) -> T;
+
+    /// See [`BitSetLike::contains`](trait.BitSetLike.html#tymethod.contains).
+    fn dyn_contains(&self, i: Index) -> bool;
+}
+
+impl<B, const L: usize> DynBitSetLike<B::Underlying, L> for B
+where
+    B: BitSetLike<L>,
+{
+    #[inline]
+    fn dyn_get_from_layer(&self, layer: usize, idx: usize) -> B::Underlying {
+        self.get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn dyn_get_full_from_layer(&self, layer: usize, idx: usize) -> B::Underlying {
+        self.get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn dyn_contains(&self, i: Index) -> bool {
+        self.contains(i)
+    }
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> BitSetLike<L> for dyn DynBitSetLike<T, L> + 'a {
+    type Underlying = T;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.dyn_get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.dyn_get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.dyn_contains(i)
+    }
+}
+
+/// `BitSetExpr` is an expression over borrowed [`BitSetLike`] items, built
+/// at runtime, which represents the resulting virtual set.
+///
+/// Unlike the combinators like [`BitSetAnd`], whose type spells out the
+/// whole expression, every expression has the same type, so it can be
+/// stored in a field or built from a user defined filter. The summary
+/// layers are combined the same way as the combinators do, so iterating
+/// skips the same blocks.
+///
+/// Expressions are built from sets with `From` or [`set`], and combined
+/// with the [`and`], [`or`], [`xor`] and [`and_not`] methods, or the
+/// `&`, `|`, `^`, `-` and `!` operators.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetExpr, BitSetLike};
+///
+/// let a: BitSet = (0..10).collect();
+/// let b: BitSet = (5..15).collect();
+/// let c: BitSet = (0..15).filter(|i| i % 2 == 0).collect();
+///
+/// let filter = (BitSetExpr::from(&a) & &b) | !BitSetExpr::from(&c);
+/// assert_eq!(filter.iter().take(6).collect::<Vec<_>>(), vec![1, 3, 5, 6, 7, 8]);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`BitSetAnd`]: struct.BitSetAnd.html
+/// [`set`]: #method.set
+/// [`and`]: #method.and
+/// [`or`]: #method.or
+/// [`xor`]: #method.xor
+/// [`and_not`]: #method.and_not
+#[derive(Clone)]
+pub enum BitSetExpr<'a, T: UnsignedInteger, const L: usize = LAYERS> {
+    /// The empty set.
+    Empty,
+    /// The set of every index.
+    All,
+    /// A borrowed set.
+    Set(&'a dyn DynBitSetLike<T, L>),
+    /// The intersection of two expressions.
+    And(Box<Self>, Box<Self>),
+    /// The union of two expressions.
+    Or(Box<Self>, Box<Self>),
+    /// The indices in exactly one of two expressions.
+    Xor(Box<Self>, Box<Self>),
+    /// The indices of the first expression which aren't in the second one.
+    AndNot(Box<Self>, Box<Self>),
+    /// The indices which aren't in the expression.
+    Not(Box<Self>),
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> BitSetExpr<'a, T, L> {
+    /// Creates an expression holding `set`.
+    pub fn set<S>(set: &'a S) -> Self
+    where
+        S: BitSetLike<L, Underlying = T>,
+    {
+        BitSetExpr::Set(set)
+    }
+
+    /// Intersects this expression with `other`.
+    pub fn and<E: Into<Self>>(self, other: E) -> Self {
+        BitSetExpr::And(Box::new(self), Box::new(other.into()))
+    }
+
+    /// Merges this expression with `other`.
+    pub fn or<E: Into<Self>>(self, other: E) -> Self {
+        BitSetExpr::Or(Box::new(self), Box::new(other.into()))
+    }
+
+    /// Keeps the indices in exactly one of this expression and `other`.
+    pub fn xor<E: Into<Self>>(self, other: E) -> Self {
+        BitSetExpr::Xor(Box::new(self), Box::new(other.into()))
+    }
+
+    /// Removes the indices of `other` from this expression.
+    pub fn and_not<E: Into<Self>>(self, other: E) -> Self {
+        BitSetExpr::AndNot(Box::new(self), Box::new(other.into()))
+    }
+
+    /// Intersects all of `exprs`. The intersection of no expressions is empty.
+    pub fn and_all<I>(exprs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Self>,
+    {
+        let mut exprs = exprs.into_iter().map(Into::into);
+        match exprs.next() {
+            Some(first) => exprs.fold(first, BitSetExpr::and),
+            None => BitSetExpr::Empty,
+        }
+    }
+
+    /// Merges all of `exprs`.
+    pub fn or_all<I>(exprs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Self>,
+    {
+        exprs.into_iter().fold(BitSetExpr::Empty, BitSetExpr::or)
+    }
+
+    /// Returns the words of the summary and of the full block summary
+    /// corresponding to layer and index.
+    fn words(&self, layer: usize, idx: usize) -> (T, T) {
+        let top = |word: T| {
+            if layer == L - 1 {
+                word & top_mask(L)
+            } else {
+                word
+            }
+        };
+        match *self {
+            BitSetExpr::Empty => (T::ZERO, T::ZERO),
+            BitSetExpr::All => (top(T::MAX), top(T::MAX)),
+            BitSetExpr::Set(set) => (
+                set.dyn_get_from_layer(layer, idx),
+                set.dyn_get_full_from_layer(layer, idx),
+            ),
+            BitSetExpr::And(ref a, ref b) => {
+                let ((a, a_full), (b, b_full)) = (a.words(layer, idx), b.words(layer, idx));
+                (a & b, a_full & b_full)
+            }
+            BitSetExpr::Or(ref a, ref b) => {
+                let ((a, a_full), (b, b_full)) = (a.words(layer, idx), b.words(layer, idx));
+                (a | b, a_full | b_full)
+            }
+            BitSetExpr::Xor(ref a, ref b) => {
+                let ((a, a_full), (b, b_full)) = (a.words(layer, idx), b.words(layer, idx));
+                ((a | b) & !(a_full & b_full), (a_full | b_full) & !(a & b))
+            }
+            BitSetExpr::AndNot(ref a, ref b) => {
+                let ((a, a_full), (b, b_full)) = (a.words(layer, idx), b.words(layer, idx));
+                (a & !b_full, a_full & !b)
+            }
+            BitSetExpr::Not(ref a) => {
+                let (a, a_full) = a.words(layer, idx);
+                (top(!a_full), top(!a))
+            }
+        }
+    }
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> BitSetLike<L> for BitSetExpr<'a, T, L> {
+    type Underlying = T;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.words(layer, idx).0
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.words(layer, idx).1
+    }
+    fn contains(&self, i: Index) -> bool {
+        match *self {
+            BitSetExpr::Empty => false,
+            BitSetExpr::All => true,
+            BitSetExpr::Set(set) => set.dyn_contains(i),
+            BitSetExpr::And(ref a, ref b) => a.contains(i) && b.contains(i),
+            BitSetExpr::Or(ref a, ref b) => a.contains(i) || b.contains(i),
+            BitSetExpr::Xor(ref a, ref b) => a.contains(i) != b.contains(i),
+            BitSetExpr::AndNot(ref a, ref b) => a.contains(i) && !b.contains(i),
+            BitSetExpr::Not(ref a) => !a.contains(i),
+        }
+    }
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> Debug for BitSetExpr<'a, T, L> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            BitSetExpr::Empty => f.write_str("Empty"),
+            BitSetExpr::All => f.write_str("All"),
+            BitSetExpr::Set(_) => f.write_str("Set(..)"),
+            BitSetExpr::And(ref a, ref b) => f.debug_tuple("And").field(a).field(b).finish(),
+            BitSetExpr::Or(ref a, ref b) => f.debug_tuple("Or").field(a).field(b).finish(),
+            BitSetExpr::Xor(ref a, ref b) => f.debug_tuple("Xor").field(a).field(b).finish(),
+            BitSetExpr::AndNot(ref a, ref b) => f.debug_tuple("AndNot").field(a).field(b).finish(),
+            BitSetExpr::Not(ref a) => f.debug_tuple("Not").field(a).finish(),
+        }
+    }
+}
+
+impl<'a, S, const L: usize> From<&'a S> for BitSetExpr<'a, S::Underlying, L>
+where
+    S: BitSetLike<L>,
+{
+    fn from(set: &'a S) -> Self {
+        BitSetExpr::Set(set)
+    }
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> IntoIterator for BitSetExpr<'a, T, L> {
+    type Item = Index;
+    type IntoIter = BitIter<Self, L>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, 'b, T: UnsignedInteger, const L: usize> IntoIterator for &'b BitSetExpr<'a, T, L> {
+    type Item = Index;
+    type IntoIter = BitIter<Self, L>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T: UnsignedInteger, const L: usize> Not for BitSetExpr<'a, T, L> {
+    type Output = Self;
+    fn not(self) -> Self {
+        BitSetExpr::Not(Box::new(self))
+    }
+}
+
+impl<'a, T: UnsignedInteger, E: Into<Self>, const L: usize> BitAnd<E> for BitSetExpr<'a, T, L> {
+    type Output = Self;
+    fn bitand(self, rhs: E) -> Self {
+        self.and(rhs)
+    }
+}
+
+impl<'a, T: UnsignedInteger, E: Into<Self>, const L: usize> BitOr<E> for BitSetExpr<'a, T, L> {
+    type Output = Self;
+    fn bitor(self, rhs: E) -> Self {
+        self.or(rhs)
+    }
+}
+
+impl<'a, T: UnsignedInteger, E: Into<Self>, const L: usize> BitXor<E> for BitSetExpr<'a, T, L> {
+    type Output = Self;
+    fn bitxor(self, rhs: E) -> Self {
+        self.xor(rhs)
+    }
+}
+
+impl<'a, T: UnsignedInteger, E: Into<Self>, const L: usize> Sub<E> for BitSetExpr<'a, T, L> {
+    type Output = Self;
+    fn sub(self, rhs: E) -> Self {
+        self.and_not(rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use super::{BitSetExpr, DynBitSetLike};
+    use {
+        AtomicBitSet, BitSetAnd, BitSetLike, BitSetNot, BitSetOr, BitSetXor, GenericBitSet, Index,
+        UnsignedInteger,
+    };
+
+    #[test_with(u32, u64, usize, u128)]
+    fn matches_combinators<T: UnsignedInteger>() {
+        let a: GenericBitSet<T> = (0..50_000).filter(|i| i % 3 == 0).collect();
+        let b: GenericBitSet<T> = (20_000..70_000).collect();
+        let c: GenericBitSet<T> = (0..100_000).filter(|i| i % 7 != 0).collect();
+
+        let expr = (BitSetExpr::from(&a) & &b) | !BitSetExpr::from(&c);
+        let typed = BitSetOr(BitSetAnd(&a, &b), BitSetNot(&c));
+        assert!(expr.set_eq(&typed));
+        assert!((&expr)
+            .iter_range(..200_000)
+            .eq((&typed).iter_range(..200_000)));
+        assert_eq!(expr.contains(21), typed.contains(21));
+
+        let expr = (BitSetExpr::from(&a) ^ &b) - &c;
+        let typed = BitSetAnd(BitSetXor(&a, &b), BitSetNot(&c));
+        assert!(expr.set_eq(&typed));
+        assert_eq!(
+            (&expr).iter().collect::<Vec<Index>>(),
+            typed.iter().collect::<Vec<Index>>()
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn prunes_full_blocks<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut set = GenericBitSet::<T>::new();
+        set.insert_range(0..2 * block + 1);
+
+        let not = !BitSetExpr::set(&set);
+        assert_eq!(not.get_from_layer(1, 0), T::ZERO);
+        assert_eq!(not.first(), Some(2 * block + 1));
+        assert_eq!((BitSetExpr::All - &set).first(), Some(2 * block + 1));
+        assert!((!!BitSetExpr::set(&set)).set_eq(&set));
+        assert!(BitSetExpr::<T>::Empty.is_empty());
+        assert_eq!(BitSetExpr::<T>::All.last(), Some(T::MAX_EID - 1));
+    }
+
+    #[test]
+    fn runtime_filter() {
+        struct Filter<'a> {
+            expr: BitSetExpr<'a, usize>,
+        }
+
+        let atomic: AtomicBitSet = (0..1_000).filter(|i| i % 2 == 0).collect();
+        let plain: GenericBitSet<usize> = (0..1_000).filter(|i| i % 5 == 0).collect();
+        let sets: Vec<&dyn DynBitSetLike<usize>> = vec![&atomic, &plain];
+
+        let filter = Filter {
+            expr: BitSetExpr::and_all(sets.iter().map(|&set| BitSetExpr::Set(set))),
+        };
+        assert_eq!(
+            (&filter.expr).iter().collect::<Vec<_>>(),
+            (0..1_000).step_by(10).collect::<Vec<_>>()
+        );
+        assert!(BitSetExpr::and_all(Vec::<&GenericBitSet<usize>>::new()).is_empty());
+
+        let union = BitSetExpr::or_all(vec![&plain, &plain]);
+        assert!(union.set_eq(&plain));
+        assert_eq!(sets[0].next_set(1), Some(2));
+        assert_eq!(
+            format!("{:?}", !BitSetExpr::from(&plain).and(&plain)),
+            "Not(And(Set(..), Set(..)))"
+        );
+    }
+}
@@ -5,25 +5,25 @@ use {BitSetLike, DrainableBitSet};
 /// A draining `Iterator` over a [`DrainableBitSet`] structure.
 ///
 /// [`DrainableBitSet`]: ../trait.DrainableBitSet.html
-pub struct DrainBitIter<'a, T: 'a + BitSetLike> {
-    iter: BitIter<&'a mut T>,
+pub struct DrainBitIter<'a, T: 'a + BitSetLike<L>, const L: usize = LAYERS> {
+    iter: BitIter<&'a mut T, L>,
 }
 
-impl<'a, T: DrainableBitSet> DrainBitIter<'a, T> {
+impl<'a, T: DrainableBitSet<L>, const L: usize> DrainBitIter<'a, T, L> {
     /// Creates a new `DrainBitIter`. You usually don't call this function
     /// but just [`.drain()`] on a bit set.
     ///
     /// [`.drain()`]: ../trait.DrainableBitSet.html#method.drain
-    pub fn new(set: &'a mut T, masks: [T::Underlying; LAYERS], prefix: [u32; LAYERS - 1]) -> Self {
+    pub fn new(set: &'a mut T, masks: [T::Underlying; L], prefix: [u32; L]) -> Self {
         DrainBitIter {
             iter: BitIter::new(set, masks, prefix),
         }
     }
 }
 
-impl<'a, T> Iterator for DrainBitIter<'a, T>
+impl<'a, T, const L: usize> Iterator for DrainBitIter<'a, T, L>
 where
-    T: DrainableBitSet,
+    T: DrainableBitSet<L>,
 {
     type Item = Index;
 
@@ -36,16 +36,40 @@ where
     }
 }
 
+impl<'a, T, const L: usize> DoubleEndedIterator for DrainBitIter<'a, T, L>
+where
+    T: DrainableBitSet<L>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let next = self.iter.next_back();
+        if let Some(next) = next {
+            self.iter.set.remove(next);
+        }
+        next
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate typed_test_gen;
     use self::typed_test_gen::test_with;
     use {BitSetLike, DrainableBitSet, GenericBitSet, UnsignedInteger};
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn drain_all<T: UnsignedInteger>() {
         let mut bit_set: GenericBitSet<T> = (0..10000).filter(|i| i % 2 == 0).collect();
         bit_set.drain().for_each(|_| {});
         assert_eq!(0, bit_set.iter().count());
     }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn drain_rev<T: UnsignedInteger>() {
+        let mut bit_set: GenericBitSet<T> = (0..10000).filter(|i| i % 2 == 0).collect();
+        let drained = bit_set.drain().rev().collect::<Vec<_>>();
+        assert_eq!(
+            drained,
+            (0..10000).filter(|i| i % 2 == 0).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(0, bit_set.iter().count());
+    }
 }
@@ -1,3 +1,7 @@
+use std::mem;
+use std::ops::RangeInclusive;
+
+use storage::Storage;
 use util::*;
 use {BitSetLike, GenericBitSet};
 
@@ -12,80 +16,248 @@ mod parallel;
 
 /// An `Iterator` over a [`BitSetLike`] structure.
 ///
+/// It can also be iterated from the back, yielding indices in descending order.
+///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 #[derive(Debug, Clone)]
-pub struct BitIter<T: BitSetLike> {
+pub struct BitIter<T: BitSetLike<L>, const L: usize = LAYERS> {
     pub(crate) set: T,
-    pub(crate) masks: [T::Underlying; LAYERS],
-    pub(crate) prefix: [u32; LAYERS - 1],
+    pub(crate) masks: [T::Underlying; L],
+    // The prefix of the top layer is always zero.
+    pub(crate) prefix: [u32; L],
+    // The back cursor. Every index it covers is greater than the
+    // ones covered by `masks`, and within it the lower layers hold
+    // the greater indices.
+    pub(crate) back_masks: [T::Underlying; L],
+    pub(crate) back_prefix: [u32; L],
 }
 
-impl<T: BitSetLike> BitIter<T> {
+impl<T: BitSetLike<L>, const L: usize> BitIter<T, L> {
     /// Creates a new `BitIter`. You usually don't call this function
     /// but just [`.iter()`] on a bit set.
     ///
+    /// The prefix of the top layer, `prefix[L - 1]`, must be zero.
+    ///
     /// [`.iter()`]: ../trait.BitSetLike.html#method.iter
-    pub fn new(set: T, masks: [T::Underlying; LAYERS], prefix: [u32; LAYERS - 1]) -> Self {
+    pub fn new(set: T, masks: [T::Underlying; L], prefix: [u32; L]) -> Self {
         BitIter {
-            set: set,
-            masks: masks,
-            prefix: prefix,
+            set,
+            masks,
+            prefix,
+            back_masks: [T::Underlying::ZERO; L],
+            back_prefix: [0; L],
+        }
+    }
+
+    /// Creates a new `BitIter` over the indices of `set` that lie within
+    /// `range`. You usually don't call this function but just
+    /// [`.iter_range()`] on a bit set.
+    ///
+    /// [`.iter_range()`]: ../trait.BitSetLike.html#method.iter_range
+    pub fn new_range(set: T, range: RangeInclusive<Index>) -> Self {
+        let mut iter = BitIter::new(set, [T::Underlying::ZERO; L], [0; L]);
+        let bits = T::Underlying::LOG_BITS;
+        let max = (capacity::<T::Underlying>(L) - 1) as usize;
+        let (start, end) = (*range.start() as usize, max.min(*range.end() as usize));
+        if start > end {
+            return iter;
+        }
+
+        let row = |i: usize, level: usize| (i >> (bits * level)) & ((1 << bits) - 1);
+        let word = |i: usize, level: usize| i >> (bits * (level + 1));
+        let bit = |row: usize| T::Underlying::ONE << T::Underlying::from_u32(row as u32);
+        let from = |row: usize| T::Underlying::MAX << T::Underlying::from_u32(row as u32);
+        let to = |row: usize| {
+            T::Underlying::MAX >> T::Underlying::from_u32((T::Underlying::BITS - 1 - row) as u32)
+        };
+
+        // Walk down the path `start` and `end` share, until they part.
+        let mut level = L - 1;
+        loop {
+            let mask = iter.set.get_from_layer(level, word(start, level));
+            let (first, last) = (row(start, level), row(end, level));
+            if level == 0 {
+                iter.masks[0] = mask & from(first) & to(last);
+                iter.prefix[0] = (word(start, 0) << bits) as u32;
+                return iter;
+            }
+            if first != last {
+                // The front takes everything strictly between the two paths
+                // and the back takes what remains on the path to `end`.
+                iter.masks[level] = mask & from(first) & to(last) & !bit(first) & !bit(last);
+                if level < L - 1 {
+                    iter.prefix[level] = (word(start, level) << bits) as u32;
+                }
+                let (mut start_live, mut end_live) = (
+                    mask & bit(first) != T::Underlying::ZERO,
+                    mask & bit(last) != T::Underlying::ZERO,
+                );
+                for lower in (0..level).rev() {
+                    if start_live {
+                        let mask = iter.set.get_from_layer(lower, word(start, lower));
+                        let first = row(start, lower);
+                        iter.masks[lower] = match lower {
+                            0 => mask & from(first),
+                            _ => mask & from(first) & !bit(first),
+                        };
+                        iter.prefix[lower] = (word(start, lower) << bits) as u32;
+                        start_live = mask & bit(first) != T::Underlying::ZERO;
+                    }
+                    if end_live {
+                        let mask = iter.set.get_from_layer(lower, word(end, lower));
+                        let last = row(end, lower);
+                        iter.back_masks[lower] = match lower {
+                            0 => mask & to(last),
+                            _ => mask & to(last) & !bit(last),
+                        };
+                        iter.back_prefix[lower] = (word(end, lower) << bits) as u32;
+                        end_live = mask & bit(last) != T::Underlying::ZERO;
+                    }
+                }
+                return iter;
+            }
+            if mask & bit(first) == T::Underlying::ZERO {
+                // Nothing is set on the shared path
+                return iter;
+            }
+            level -= 1;
         }
     }
 }
 
-impl<T: BitSetLike> BitIter<T> {
+impl<T: BitSetLike<L>, const L: usize> BitIter<T, L> {
     /// Allows checking if set bit is contained in underlying bit set.
     pub fn contains(&self, i: Index) -> bool {
         self.set.contains(i)
     }
 }
 
-impl<'a, T: UnsignedInteger> BitIter<&'a mut GenericBitSet<T>> {
+impl<T, const L: usize, S> BitIter<&mut GenericBitSet<T, L, S>, L>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     /// Clears the rest of the bitset starting from the next inner layer.
+    ///
+    /// Only the layer 0 words are cleared, the storage clears the summary
+    /// bits above them.
     pub(crate) fn clear(&mut self) {
         use self::State::Continue;
-        while let Some(level) = (1..LAYERS).find(|&level| self.handle_level(level) == Continue) {
-            let lower = level - 1;
-            let idx = (self.prefix[lower] >> T::LOG_BITS) as usize;
-            *self.set.layer_mut(lower, idx) = T::ZERO;
-            if level == LAYERS - 1 {
-                self.set.layer3 &= T::from_u64(!((2usize << idx) - 1) as u64);
+        while let Some(level) = (1..L).find(|&level| self.handle_level(level) == Continue) {
+            if level == 1 {
+                let idx = (self.prefix[0] >> T::LOG_BITS) as usize;
+                self.set.set_layer0(idx, T::ZERO);
             }
         }
     }
 }
 
+/// An `Iterator` over the non-zero layer 0 words of a [`BitSetLike`],
+/// yielding each together with its offset in layer 0.
+///
+/// Only the words below set summary bits are visited.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+pub(crate) struct WordIter<T: BitSetLike<L>, const L: usize = LAYERS>(BitIter<T, L>);
+
+impl<T: BitSetLike<L>, const L: usize> WordIter<T, L> {
+    pub(crate) fn new(set: T) -> Self {
+        WordIter(set.iter())
+    }
+}
+
+impl<T: BitSetLike<L>, const L: usize> Iterator for WordIter<T, L> {
+    type Item = (usize, T::Underlying);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use self::State::Continue;
+        let iter = &mut self.0;
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            if level == 1 && iter.masks[0] != T::Underlying::ZERO {
+                let word = mem::replace(&mut iter.masks[0], T::Underlying::ZERO);
+                return Some((iter.prefix[0] as usize >> T::Underlying::LOG_BITS, word));
+            }
+        }
+        None
+    }
+}
+
+/// The outcome of a step of an iterator cursor on a layer, `I` being the
+/// type of the indices it yields.
 #[derive(PartialEq)]
-pub(crate) enum State {
+pub(crate) enum State<I = Index> {
     Empty,
     Continue,
-    Value(Index),
+    Value(I),
 }
 
-impl<T> Iterator for BitIter<T>
+impl<T, const L: usize> Iterator for BitIter<T, L>
 where
-    T: BitSetLike,
+    T: BitSetLike<L>,
 {
     type Item = Index;
 
     fn next(&mut self) -> Option<Self::Item> {
         use self::State::*;
         'find: loop {
-            for level in 0..LAYERS {
+            for level in 0..L {
                 match self.handle_level(level) {
                     Value(v) => return Some(v),
                     Continue => continue 'find,
                     Empty => {}
                 }
             }
+            // The front is exhausted, continue with the
+            // lowest bits the back cursor hasn't visited yet
+            if let Some(level) = (0..L)
+                .rev()
+                .find(|&level| self.back_masks[level] != T::Underlying::ZERO)
+            {
+                self.masks[level] = mem::replace(&mut self.back_masks[level], T::Underlying::ZERO);
+                if level < L - 1 {
+                    self.prefix[level] = self.back_prefix[level];
+                }
+                continue;
+            }
             // There is no set bits left
             return None;
         }
     }
 }
 
-impl<T: BitSetLike> BitIter<T> {
+impl<T, const L: usize> DoubleEndedIterator for BitIter<T, L>
+where
+    T: BitSetLike<L>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        use self::State::*;
+        'find: loop {
+            for level in 0..L {
+                match self.handle_level_back(level) {
+                    Value(v) => return Some(v),
+                    Continue => continue 'find,
+                    Empty => {}
+                }
+            }
+            // The back is exhausted, continue with the
+            // highest bits the front hasn't visited yet
+            if let Some(level) = (0..L)
+                .rev()
+                .find(|&level| self.masks[level] != T::Underlying::ZERO)
+            {
+                self.back_masks[level] = mem::replace(&mut self.masks[level], T::Underlying::ZERO);
+                if level < L - 1 {
+                    self.back_prefix[level] = self.prefix[level];
+                }
+                continue;
+            }
+            // There is no set bits left
+            return None;
+        }
+    }
+}
+
+impl<T: BitSetLike<L>, const L: usize> BitIter<T, L> {
     pub(crate) fn handle_level(&mut self, level: usize) -> State {
         use self::State::*;
         if self.masks[level] == T::Underlying::ZERO {
@@ -108,6 +280,30 @@ impl<T: BitSetLike> BitIter<T> {
             }
         }
     }
+
+    pub(crate) fn handle_level_back(&mut self, level: usize) -> State {
+        use self::State::*;
+        if self.back_masks[level] == T::Underlying::ZERO {
+            Empty
+        } else {
+            // Take the last bit that isn't zero
+            let last_bit =
+                (T::Underlying::BITS - 1) as u32 - self.back_masks[level].leading_zeros();
+            // Remove it from the mask
+            self.back_masks[level] &= !(T::Underlying::ONE << T::Underlying::from_u32(last_bit));
+            // Calculate the index of it
+            let idx = self.back_prefix.get(level).cloned().unwrap_or(0) | last_bit;
+            if level == 0 {
+                // It's the lowest layer, so the `idx` is the previous set bit
+                Value(idx)
+            } else {
+                // Take the corresponding `usize` from the layer below
+                self.back_masks[level - 1] = self.set.get_from_layer(level - 1, idx as usize);
+                self.back_prefix[level - 1] = idx << T::Underlying::LOG_BITS;
+                Continue
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -115,9 +311,9 @@ mod tests {
     extern crate typed_test_gen;
     use self::typed_test_gen::test_with;
 
-    use {BitSetLike, GenericBitSet, UnsignedInteger};
+    use {BitSetLike, BitSetNot, GenericBitSet, Index, UnsignedInteger};
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iterator_clear_empties<T: UnsignedInteger>() {
         use rand::prelude::*;
 
@@ -128,19 +324,124 @@ mod tests {
             set.add(rng.gen_range(0, limit));
         }
         (&mut set).iter().clear();
-        assert_eq!(T::ZERO, set.layer3);
-        for &i in &set.layer2 {
-            assert_eq!(T::ZERO, i);
+        for layer in &set.storage.layers {
+            for &i in layer {
+                assert_eq!(T::ZERO, i);
+            }
+        }
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_rev<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let mut set = GenericBitSet::<T>::new();
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for _ in 0..(limit / 10) {
+            set.add(rng.gen_range(0, limit));
         }
-        for &i in &set.layer1 {
-            assert_eq!(T::ZERO, i);
+        let mut forward = (&set).iter().collect::<Vec<_>>();
+        forward.reverse();
+        assert_eq!(forward, (&set).iter().rev().collect::<Vec<_>>());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_double_ended<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        let indices = [0, 1, 63, 64, 65, 4095, 4096, 100_000, 262_143, 1_000_000];
+        for &i in &indices {
+            set.add(i);
         }
-        for &i in &set.layer0 {
-            assert_eq!(T::ZERO, i);
+
+        // meet in every possible position
+        for split in 0..=indices.len() {
+            let mut iter = (&set).iter();
+            let front = iter.by_ref().take(split).collect::<Vec<_>>();
+            let mut back = iter.rev().collect::<Vec<_>>();
+            back.reverse();
+            assert_eq!(front, &indices[..split]);
+            assert_eq!(back, &indices[split..]);
         }
+
+        // alternate between both ends
+        let mut iter = (&set).iter();
+        let mut collected = Vec::new();
+        loop {
+            match (iter.next(), iter.next_back()) {
+                (Some(a), Some(b)) => {
+                    collected.push(a);
+                    collected.push(b);
+                }
+                (Some(a), None) => collected.push(a),
+                (None, Some(b)) => collected.push(b),
+                (None, None) => break,
+            }
+        }
+        collected.sort();
+        assert_eq!(collected, indices);
+
+        // the back cursor is drained by the front
+        let mut iter = (&set).iter();
+        assert_eq!(iter.next_back(), Some(1_000_000));
+        assert_eq!(iter.collect::<Vec<_>>(), &indices[..indices.len() - 1]);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_range<T: UnsignedInteger>() {
+        use rand::prelude::*;
+        use std::ops::Bound::*;
+
+        let mut set = GenericBitSet::<T>::new();
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for _ in 0..(limit / 100) {
+            set.add(rng.gen_range(0, limit));
+        }
+        set.add(0);
+        set.add(limit - 1);
+        let members = (&set).iter().collect::<Vec<_>>();
+
+        for _ in 0..100 {
+            let a = rng.gen_range(0, limit);
+            let b = rng.gen_range(a, limit);
+            let expected = members
+                .iter()
+                .cloned()
+                .filter(|&i| a <= i && i < b)
+                .collect::<Vec<_>>();
+            assert_eq!((&set).iter_range(a..b).collect::<Vec<_>>(), expected);
+            let mut rev = (&set).iter_range(a..b).rev().collect::<Vec<_>>();
+            rev.reverse();
+            assert_eq!(rev, expected);
+        }
+
+        assert_eq!((&set).iter_range(..).collect::<Vec<_>>(), members);
+        assert_eq!((&set).iter_range(0..=0).collect::<Vec<_>>(), [0]);
+        assert_eq!(
+            (&set).iter_range(limit - 1..).collect::<Vec<_>>(),
+            [limit - 1]
+        );
+        assert_eq!((&set).iter_range(5..5).count(), 0);
+        assert_eq!((&set).iter_range(Index::MAX..).count(), 0);
+        assert_eq!(
+            (&set).iter_range((Excluded(0), Unbounded)).count(),
+            members.len() - 1
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_range_not<T: UnsignedInteger>() {
+        let set = GenericBitSet::<T>::new();
+        let not = BitSetNot(&set);
+        let start = 1 << (T::LOG_BITS * 2);
+        let collected = (&not)
+            .iter_range(start - 10..start + 10)
+            .collect::<Vec<_>>();
+        assert_eq!(collected, (start - 10..start + 10).collect::<Vec<_>>());
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iterator_clone<T: UnsignedInteger>() {
         let mut set = GenericBitSet::<T>::new();
         set.add(1);
@@ -1,17 +1,20 @@
+use std::mem;
+use std::ops::{RangeBounds, RangeInclusive};
+
 use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
 use rayon::iter::ParallelIterator;
 
-use iter::{BitIter, Index, LAYERS};
-use util::average_ones;
+use iter::{BitIter, Index};
+use util::{average_ones, inclusive_range, LAYERS};
 use {BitSetLike, UnsignedInteger};
 
 /// A `ParallelIterator` over a [`BitSetLike`] structure.
 ///
 /// [`BitSetLike`]: ../../trait.BitSetLike.html
 #[derive(Debug)]
-pub struct BitParIter<T>(T, u8);
+pub struct BitParIter<T, const L: usize = LAYERS>(T, u8, Option<RangeInclusive<Index>>);
 
-impl<T> BitParIter<T> {
+impl<T, const L: usize> BitParIter<T, L> {
     /// Creates a new `BitParIter`. You usually don't call this function
     /// but just [`.par_iter()`] on a bit set.
     ///
@@ -19,7 +22,29 @@ impl<T> BitParIter<T> {
     ///
     /// [`.par_iter()`]: ../../trait.BitSetLike.html#method.par_iter
     pub fn new(set: T) -> Self {
-        BitParIter(set, 3)
+        BitParIter(set, 3, None)
+    }
+
+    /// Restricts the iteration to the indices within `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rayon;
+    /// # extern crate hibitset;
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// # use rayon::iter::ParallelIterator;
+    /// # fn main() {
+    /// let bitset: BitSet = (0..100_000).collect();
+    /// let count = bitset.par_iter()
+    ///     .range(10_000..20_000)
+    ///     .count();
+    /// assert_eq!(count, 10_000);
+    /// # }
+    /// ```
+    pub fn range<R: RangeBounds<Index>>(mut self, range: R) -> Self {
+        self.2 = Some(inclusive_range(range));
+        self
     }
 
     /// Sets how many layers are split when forking.
@@ -39,7 +64,8 @@ impl<T> BitParIter<T> {
     /// # }
     /// ```
     ///
-    /// The value should be in range [1, 3]
+    /// The value should be in range [1, L - 1], where L is the number of layers.
+    /// With the default of 4 layers:
     ///
     /// | splits | largest smallest unit of work |
     /// |--------|-------------------------------|
@@ -49,16 +75,16 @@ impl<T> BitParIter<T> {
     ///
     pub fn layers_split(mut self, layers: u8) -> Self {
         assert!(layers >= 1);
-        assert!(layers <= 3);
+        assert!((layers as usize) < L);
         self.1 = layers;
         self
     }
 }
 
-impl<T> ParallelIterator for BitParIter<T>
+impl<T, const L: usize> ParallelIterator for BitParIter<T, L>
 where
-    T: BitSetLike + Send + Sync,
-    <T as BitSetLike>::Underlying: Send + Sync,
+    T: BitSetLike<L> + Send + Sync,
+    <T as BitSetLike<L>>::Underlying: Send + Sync,
 {
     type Item = Index;
 
@@ -66,7 +92,11 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        bridge_unindexed(BitProducer((&self.0).iter(), self.1), consumer)
+        let iter = match self.2 {
+            Some(range) => (&self.0).iter_range(range),
+            None => (&self.0).iter(),
+        };
+        bridge_unindexed(BitProducer(iter, self.1), consumer)
     }
 }
 
@@ -74,17 +104,24 @@ where
 ///
 /// Usually used internally by `BitParIter`.
 #[derive(Debug)]
-pub struct BitProducer<'a, T: 'a + BitSetLike>(pub BitIter<&'a T>, pub u8);
+pub struct BitProducer<'a, T: 'a + BitSetLike<L>, const L: usize = LAYERS>(
+    pub BitIter<&'a T, L>,
+    pub u8,
+);
 
-impl<'a, T: 'a + Send + Sync> UnindexedProducer for BitProducer<'a, T>
+impl<'a, T: 'a + Send + Sync, const L: usize> UnindexedProducer for BitProducer<'a, T, L>
 where
-    T: BitSetLike,
-    <T as BitSetLike>::Underlying: Send + Sync,
+    T: BitSetLike<L>,
+    <T as BitSetLike<L>>::Underlying: Send + Sync,
 {
     type Item = Index;
 
     /// How the splitting is done:
     ///
+    /// 0) An iterator restricted to a range may cover several layers
+    ///    and be iterated from both ends. Those parts are split off
+    ///    first, the back before the highest layer of the front.
+    ///
     /// 1) First the highest layer that has at least one set bit
     ///    is searched.
     ///
@@ -113,6 +150,9 @@ where
     /// then it will produce non-optimal splittings.
     fn split(mut self) -> (Self, Option<Self>) {
         let splits = self.1;
+        if let Some(other) = self.split_off() {
+            return (self, Some(other));
+        }
         let other = {
             let mut handle_level = |level: usize| {
                 if self.0.masks[level] == T::Underlying::ZERO {
@@ -120,17 +160,13 @@ where
                     None
                 } else {
                     // Top levels prefix is zero because there is nothing before it
-                    let level_prefix = self.0.prefix.get(level).cloned().unwrap_or(0);
+                    let level_prefix = self.0.prefix[level];
                     let first_bit = self.0.masks[level].trailing_zeros();
                     average_ones(self.0.masks[level])
-                        .and_then(|average_bit| {
+                        .map(|average_bit| {
                             let mask = (T::Underlying::ONE << average_bit) - T::Underlying::ONE;
                             let mut other = BitProducer(
-                                BitIter::new(
-                                    self.0.set,
-                                    [T::Underlying::ZERO; LAYERS],
-                                    [0; LAYERS - 1],
-                                ),
+                                BitIter::new(self.0.set, [T::Underlying::ZERO; L], [0; L]),
                                 splits,
                             );
                             // The `other` is the more significant half of the mask
@@ -144,7 +180,7 @@ where
                             self.0.masks[level] &= mask;
                             self.0.prefix[level - 1] =
                                 (level_prefix | first_bit) << T::Underlying::LOG_BITS;
-                            Some(other)
+                            other
                         })
                         .or_else(|| {
                             // Because there is only one bit left we descend to it
@@ -158,7 +194,7 @@ where
                         })
                 }
             };
-            let top_layer = LAYERS - 1;
+            let top_layer = L - 1;
             let mut h = handle_level(top_layer);
             for i in 1..splits {
                 h = h.or_else(|| handle_level(top_layer - i as usize));
@@ -176,6 +212,44 @@ where
     }
 }
 
+impl<'a, T: 'a + BitSetLike<L>, const L: usize> BitProducer<'a, T, L> {
+    /// Splits off the back of the iterator, or the highest layer
+    /// of its front if more than one layer is left to iterate.
+    ///
+    /// Returns `None` if either half would be left empty.
+    fn split_off(&mut self) -> Option<Self> {
+        let zero = T::Underlying::ZERO;
+        if self.0.masks.iter().all(|&mask| mask == zero) {
+            // The front is exhausted, so it takes over the lowest bits of
+            // the back, as in `next`, to leave something on both sides.
+            if let Some(level) = (0..L).rev().find(|&level| self.0.back_masks[level] != zero) {
+                self.0.masks[level] = mem::replace(&mut self.0.back_masks[level], zero);
+                if level < L - 1 {
+                    self.0.prefix[level] = self.0.back_prefix[level];
+                }
+            }
+        }
+        let mut other = BitProducer(BitIter::new(self.0.set, [zero; L], [0; L]), self.1);
+        if self.0.back_masks.iter().any(|&mask| mask != zero) {
+            // The back only holds indices greater than the front
+            other.0.back_masks = self.0.back_masks;
+            other.0.back_prefix = self.0.back_prefix;
+            self.0.back_masks = [zero; L];
+            return Some(other);
+        }
+        let mut levels = (0..L).rev().filter(|&level| self.0.masks[level] != zero);
+        match (levels.next(), levels.next()) {
+            (Some(level), Some(_)) => {
+                other.0.masks[level] = self.0.masks[level];
+                other.0.prefix[level..].copy_from_slice(&self.0.prefix[level..]);
+                self.0.masks[level] = zero;
+                Some(other)
+            }
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_bit_producer {
     extern crate typed_test_gen;
@@ -203,7 +277,7 @@ mod test_bit_producer {
                     trail.push_str(&i.to_string());
                     visit(them, d, i - j, trail, c);
                 }
-                trail.push_str("u");
+                trail.push('u');
                 visit(us, d - 1, T::LOG_BITS, trail, c);
             }
         }
@@ -236,18 +310,33 @@ mod test_bit_producer {
         assert_eq!(uint_bits.pow(split_levels as u32 - 1) * 2, count);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn max_3_splitting_of_two_top_bits<T: UnsignedInteger + Send + Sync>() {
         test_splitting::<T>(3);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn max_2_splitting_of_two_top_bits<T: UnsignedInteger + Send + Sync>() {
         test_splitting::<T>(2);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn max_1_splitting_of_two_top_bits<T: UnsignedInteger + Send + Sync>() {
         test_splitting::<T>(1);
     }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn split_after_next_back<T: UnsignedInteger + Send + Sync>() {
+        let set: GenericBitSet<T> = (0..1_000).chain(100_000..101_000).collect();
+        let mut iter = (&set).iter();
+        assert_eq!(iter.next_back(), Some(100_999));
+        // The front is exhausted and only the back is left.
+        let (us, them) = BitProducer(iter, 3).split();
+        let them = them.expect("Splitting the back");
+        let (mut us, them): (Vec<_>, Vec<_>) = (us.0.collect(), them.0.collect());
+        assert!(!us.is_empty() && !them.is_empty());
+        us.extend(them);
+        us.sort();
+        assert!(us.iter().cloned().eq((0..1_000).chain(100_000..100_999)));
+    }
 }
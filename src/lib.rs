@@ -19,8 +19,10 @@
 //! one bit for each `usize` bits of Layer 0. If any bit in that `usize`
 //! of Layer 0 is set, the bit in Layer 1 will be set.
 //!
-//! There are, in total, four layers. Layers 1 through 3 are each a
-//! summary of the layer immediately below them.
+//! There are, by default, four layers. Layers 1 through 3 are each a
+//! summary of the layer immediately below them. `GenericBitSet` takes the
+//! number of layers as a const parameter, so more can be used when the
+//! indices don't fit in four layers.
 //!
 //! ```no_compile
 //! Example, with an imaginary 4-bit usize:
@@ -49,32 +51,88 @@
 extern crate rand;
 #[cfg(feature = "parallel")]
 extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
 mod atomic;
+mod bytes;
+mod error;
+mod expr;
 mod iter;
 mod ops;
+mod paged;
+mod range;
+#[cfg(feature = "serde")]
+mod serialize;
+mod storage;
 mod util;
+mod wide;
 
-pub use atomic::AtomicBitSet;
+pub use atomic::{AtomicBitSet, GenericAtomicBitSet};
+pub use error::{CapacityError, DecodeError};
+pub use expr::{BitSetExpr, DynBitSetLike};
 pub use iter::{BitIter, DrainBitIter};
 #[cfg(feature = "parallel")]
 pub use iter::{BitParIter, BitProducer};
-pub use ops::{BitSetAll, BitSetAnd, BitSetNot, BitSetOr, BitSetXor};
+pub use ops::{
+    BitSetAll, BitSetAnd, BitSetAndAll, BitSetAndNot, BitSetNot, BitSetOr, BitSetOrAll,
+    BitSetThreshold, BitSetXor,
+};
+pub use paged::{GenericPagedBitSet, PagedBitSet};
+pub use range::{BitSetIntervals, BitSetRange};
+pub use wide::{GenericWideBitSet, WideBitIter, WideBitSet, WideBitSetLike};
+#[cfg(feature = "parallel")]
+pub use wide::{WideBitParIter, WideBitProducer};
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds, RangeInclusive};
 
+use iter::WordIter;
+use storage::{DenseStorage, Storage};
 use util::*;
 
 /// A `GenericBitSet` is a simple set designed to track which indices are placed
 /// into it. Is is based on an underlying type `T` which is supposed to represent an
-/// unsigned integer type (in particular `u32`, `u64`, or `usize`).
+/// unsigned integer type (`u8`, `u16`, `u32`, `u64`, `u128` or `usize`).
 ///
-/// Note, a `BitSet` is limited by design to only `T::NB_BITS**4` indices.
-/// Adding beyond this limit will cause the `BitSet` to panic.
-#[derive(Clone, Debug, Default)]
-pub struct GenericBitSet<T: UnsignedInteger> {
-    layer3: T,
-    layer2: Vec<T>,
-    layer1: Vec<T>,
-    layer0: Vec<T>,
+/// The number of layers `L` defaults to 4. A `GenericBitSet` is limited by
+/// design to `T::BITS**L` indices, or all of the `Index` values if that is
+/// more. Adding beyond this limit will cause the `BitSet` to panic.
+///
+/// The words of the layers are kept in a storage `S`, which defaults to a
+/// vector per layer growing with the largest index. A
+/// [`GenericPagedBitSet`] keeps them in pages allocated on demand instead,
+/// with the same operations. Only the methods exposing or sizing the
+/// vectors, like [`capacity`] and [`layer_as_slice`], are specific to the
+/// default storage.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSetLike, GenericBitSet};
+///
+/// // Five layers of `u64` hold up to 2^30 indices instead of 2^24.
+/// let mut set = GenericBitSet::<u64, 5>::new();
+/// set.add(100_000_000);
+/// assert_eq!(set.iter().collect::<Vec<_>>(), vec![100_000_000]);
+/// ```
+///
+/// [`GenericPagedBitSet`]: type.GenericPagedBitSet.html
+/// [`capacity`]: #method.capacity
+/// [`layer_as_slice`]: #method.layer_as_slice
+#[derive(Clone, Debug)]
+pub struct GenericBitSet<T, const L: usize = LAYERS, S = DenseStorage<T, L>>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
+    storage: S,
+    len: usize,
+    marker: PhantomData<T>,
 }
 
 /// A `BitSet` is a simple set designed to track which indices are placed
@@ -84,165 +142,497 @@ pub struct GenericBitSet<T: UnsignedInteger> {
 /// Adding beyond this limit will cause the `BitSet` to panic.
 pub type BitSet = GenericBitSet<usize>;
 
-impl<T: UnsignedInteger> GenericBitSet<T> {
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> Default for GenericBitSet<T, L, S> {
+    fn default() -> Self {
+        let () = Self::LAYERS_CHECK;
+        GenericBitSet {
+            storage: S::default(),
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> GenericBitSet<T, L> {
     /// Creates an empty `BitSet`.
     pub fn new() -> Self {
         Default::default()
     }
 
-    #[inline]
-    fn valid_range(max: Index) {
-        if T::MAX_EID < max {
-            panic!(
-                "Expected index to be less then {}, found {}",
-                T::MAX_EID,
-                max
-            );
+    /// Creates an empty `BitSet`, preallocated for the indices below `max`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is larger than the number of indices the set can hold.
+    pub fn with_capacity(max: Index) -> Self {
+        let mut value = Self::new();
+        if max > 0 {
+            value.extend(max - 1);
         }
+        value
     }
 
-    /// Creates an empty `BitSet`, preallocated for up to `max` indices.
-    pub fn with_capacity(max: Index) -> Self {
-        Self::valid_range(max);
+    /// Creates an empty `BitSet`, preallocated for the indices below `max`.
+    ///
+    /// Returns an error instead of panicking if `max` is larger than the
+    /// number of indices the set can hold or if the memory can't be allocated.
+    pub fn try_with_capacity(max: Index) -> Result<Self, CapacityError> {
         let mut value = Self::new();
-        value.extend(max);
-        value
+        if max > 0 {
+            value.try_grow(max - 1)?;
+        }
+        Ok(value)
+    }
+
+    /// Returns the number of indices the set can hold without reallocating.
+    ///
+    /// Every index below the returned value can be added without any of the
+    /// layers growing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let mut set = BitSet::new();
+    /// assert_eq!(set.capacity(), 0);
+    /// set.reserve(10_000);
+    /// assert!(set.capacity() > 10_000);
+    /// ```
+    pub fn capacity(&self) -> usize {
+        let capacity = (0..L)
+            .map(|level| {
+                // The full block summaries grow along with the layers above layer 0.
+                let mut words = self.storage.layers[level].capacity();
+                if level > 0 {
+                    words = words.min(self.storage.full[level].capacity());
+                }
+                (words as u64) << (T::LOG_BITS * (level + 1))
+            })
+            .min()
+            .unwrap_or(0)
+            .min(Self::CAPACITY);
+        capacity.min(usize::MAX as u64) as usize
+    }
+
+    /// Reserves memory in every layer for the set to hold indices up to
+    /// `max_index` without reallocating.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_index` is larger than what the set can hold, or if
+    /// the memory can't be allocated.
+    pub fn reserve(&mut self, max_index: Index) {
+        if let Err(err) = self.try_reserve(max_index) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Reserves memory in every layer for the set to hold indices up to
+    /// `max_index` without reallocating.
+    ///
+    /// This includes the full block summaries, so no index up to
+    /// `max_index` allocates when added afterwards.
+    ///
+    /// Returns an error instead of panicking if `max_index` can't be held
+    /// by the set or if the memory can't be allocated.
+    pub fn try_reserve(&mut self, max_index: Index) -> Result<(), CapacityError> {
+        Self::try_valid_range(max_index)?;
+        self.storage.try_reserve(max_index.offset(T::SHIFT1))
+    }
+
+    /// Grows the layers to hold `id`, reporting allocation failures.
+    #[inline(never)]
+    fn try_grow(&mut self, id: Index) -> Result<(), CapacityError> {
+        Self::try_valid_range(id)?;
+        self.storage.try_grow(id.offset(T::SHIFT1))
     }
 
     #[inline(never)]
     fn extend(&mut self, id: Index) {
         Self::valid_range(id);
-        let (p0, p1, p2) = offsets::<T>(id);
+        if let Err(err) = self.storage.try_grow(id.offset(T::SHIFT1)) {
+            panic!("{}", err);
+        }
+    }
+}
 
-        Self::fill_up(&mut self.layer2, p2);
-        Self::fill_up(&mut self.layer1, p1);
-        Self::fill_up(&mut self.layer0, p0);
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> GenericBitSet<T, L, S> {
+    /// The number of indices the set can hold.
+    const CAPACITY: u64 = capacity::<T>(L);
+
+    /// Rejects layer counts where even the top layer word
+    /// covers more than all of the `Index` values.
+    const LAYERS_CHECK: () = assert!(
+        L >= 2 && T::LOG_BITS * (L - 1) <= Index::BITS as usize,
+        "A bit set needs at least 2 layers, and no more than needed to cover every index"
+    );
+
+    #[inline]
+    fn valid_range(max: Index) {
+        if max as u64 >= Self::CAPACITY {
+            panic!(
+                "Expected index to be less then {}, found {}",
+                Self::CAPACITY,
+                max
+            );
+        }
     }
 
-    fn fill_up(vec: &mut Vec<T>, upper_index: usize) {
-        if vec.len() <= upper_index {
-            vec.resize(upper_index + 1, T::ZERO);
+    #[inline]
+    fn try_valid_range(id: Index) -> Result<(), CapacityError> {
+        if id as u64 >= Self::CAPACITY {
+            return Err(CapacityError::OutOfRange {
+                index: id,
+                max: Self::CAPACITY as Index,
+            });
         }
+        Ok(())
     }
 
-    /// This is used to set the levels in the hierarchy
-    /// when the lowest layer was set from 0.
-    #[inline(never)]
-    fn add_slow(&mut self, id: Index) {
-        let (_, p1, p2) = offsets::<T>(id);
-        self.layer1[p1] |= id.mask::<T>(T::SHIFT1);
-        self.layer2[p2] |= id.mask::<T>(T::SHIFT2);
-        self.layer3 |= id.mask::<T>(T::SHIFT3);
+    /// Creates a set holding the indices of `set`, copying whole words.
+    ///
+    /// Only the layer 0 words below the non-zero summary bits of `set` are
+    /// visited, so materializing a lazy combination of sets is much faster
+    /// than collecting its iterator one index at a time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike, BitSetNot};
+    ///
+    /// let a: BitSet = (0..100).collect();
+    /// let b: BitSet = (50..150).collect();
+    /// let set = BitSet::from_bitset_like((&a | &b) & BitSetNot(&b));
+    /// assert_eq!(set, (0..50).collect());
+    /// ```
+    pub fn from_bitset_like<B>(set: B) -> Self
+    where
+        B: BitSetLike<L, Underlying = T>,
+    {
+        use iter::State::Continue;
+        let mut value = Self::default();
+        let mut iter = set.iter();
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            if level == 1 {
+                let idx = iter.prefix[0] as usize >> T::LOG_BITS;
+                value.insert_word(idx, iter.masks[0]);
+            }
+        }
+        value
     }
 
     /// Adds `id` to the `BitSet`. Returns `true` if the value was
     /// already in the set.
     #[inline]
     pub fn add(&mut self, id: Index) -> bool {
+        Self::valid_range(id);
         let (p0, mask) = (id.offset(T::SHIFT1), id.mask::<T>(T::SHIFT0));
+        let old = self.storage.word(0, p0);
+        if old & mask != T::ZERO {
+            return true;
+        }
 
-        if p0 >= self.layer0.len() {
-            self.extend(id);
+        // the storage sets the bit on every layer to indicate
+        // that the value can be found here.
+        self.storage.set_word(p0, old | mask);
+        self.len += 1;
+        false
+    }
+
+    /// Adds `id` to the `BitSet`. Returns `Ok(true)` if the value was
+    /// already in the set.
+    ///
+    /// Unlike [`add`](#method.add), this returns an error instead of
+    /// panicking if `id` is out of range or the set can't grow. The set is
+    /// left unchanged when an error is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, CapacityError};
+    ///
+    /// let mut set = BitSet::new();
+    /// assert_eq!(set.try_add(5), Ok(false));
+    /// assert_eq!(set.try_add(5), Ok(true));
+    /// match set.try_add(u32::MAX) {
+    ///     Err(CapacityError::OutOfRange { index, .. }) => assert_eq!(index, u32::MAX),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[inline]
+    pub fn try_add(&mut self, id: Index) -> Result<bool, CapacityError> {
+        // Reserve the layers and the full block summaries up front when the
+        // set grows, so `add` can't allocate.
+        Self::try_valid_range(id)?;
+        self.storage.try_reserve(id.offset(T::SHIFT1))?;
+        Ok(self.add(id))
+    }
+
+    /// Adds every index of `iter` to the set, stopping at the first
+    /// one that can't be added.
+    ///
+    /// This is the fallible counterpart of the `Extend` implementation.
+    pub fn try_extend<I>(&mut self, iter: I) -> Result<(), CapacityError>
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        for id in iter {
+            self.try_add(id)?;
         }
+        Ok(())
+    }
 
-        if self.layer0[p0] & mask != T::ZERO {
-            return true;
+    /// Adds every index within `range` to the set.
+    ///
+    /// Whole layer 0 words are written at once, and the summary
+    /// layers are only touched for words that were previously empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the end of the range is larger than what the set can hold.
+    /// An unbounded end stands for the last index the set can hold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let mut set = BitSet::new();
+    /// set.insert_range(10..1_000);
+    /// assert_eq!(set.len(), 990);
+    /// assert!(set.contains(10) && set.contains(999) && !set.contains(1_000));
+    /// ```
+    pub fn insert_range<R: RangeBounds<Index>>(&mut self, range: R) {
+        let range = self.bounded_range(range);
+        if range.is_empty() {
+            return;
         }
+        Self::valid_range(*range.end());
+        self.update_range(range, |word, mask| word | mask);
+    }
 
-        // we need to set the bit on every layer to indicate
-        // that the value can be found here.
-        let old = self.layer0[p0];
-        self.layer0[p0] |= mask;
-        if old == T::ZERO {
-            self.add_slow(id);
+    /// Removes every index within `range` from the set.
+    ///
+    /// Whole layer 0 words are written at once, and the summary
+    /// layers are only touched for words that become empty.
+    pub fn remove_range<R: RangeBounds<Index>>(&mut self, range: R) {
+        let range = inclusive_range(range);
+        let last = match BitSetLike::last(self) {
+            Some(last) => last,
+            None => return,
+        };
+        let end = (*range.end()).min(last);
+        if *range.start() > end {
+            return;
         }
-        false
+        self.update_range(*range.start()..=end, |word, mask| word & !mask);
     }
 
-    fn layer_mut(&mut self, level: usize, idx: usize) -> &mut T {
-        match level {
-            0 => {
-                Self::fill_up(&mut self.layer0, idx);
-                &mut self.layer0[idx]
-            }
-            1 => {
-                Self::fill_up(&mut self.layer1, idx);
-                &mut self.layer1[idx]
+    /// Flips every index within `range`, adding the ones that are
+    /// not in the set and removing the ones that are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the end of the range is larger than what the set can hold.
+    /// An unbounded end stands for the last index the set can hold.
+    pub fn toggle_range<R: RangeBounds<Index>>(&mut self, range: R) {
+        let range = self.bounded_range(range);
+        if range.is_empty() {
+            return;
+        }
+        Self::valid_range(*range.end());
+        self.update_range(range, |word, mask| word ^ mask);
+    }
+
+    /// Converts `range` to an inclusive one, with an unbounded end clipped
+    /// to the last index the set can hold.
+    fn bounded_range<R: RangeBounds<Index>>(&self, range: R) -> RangeInclusive<Index> {
+        let unbounded = matches!(range.end_bound(), Bound::Unbounded);
+        let range = inclusive_range(range);
+        if unbounded {
+            *range.start()..=(Self::CAPACITY - 1) as Index
+        } else {
+            range
+        }
+    }
+
+    /// Applies `op` to every layer 0 word overlapping `range` with a mask of
+    /// the bits within the range.
+    ///
+    fn update_range<F>(&mut self, range: RangeInclusive<Index>, op: F)
+    where
+        F: Fn(T, T) -> T,
+    {
+        let (start, end) = (*range.start(), *range.end());
+        let (first, last) = (start.offset(T::SHIFT1), end.offset(T::SHIFT1));
+        for p0 in first..=last {
+            let mut mask = T::MAX;
+            if p0 == first {
+                mask &= T::MAX << start.row(T::SHIFT0);
             }
-            2 => {
-                Self::fill_up(&mut self.layer2, idx);
-                &mut self.layer2[idx]
+            if p0 == last {
+                mask &= T::MAX >> (T::from_u32(T::BITS as u32 - 1) - end.row(T::SHIFT0));
             }
-            3 => &mut self.layer3,
-            _ => panic!("Invalid layer: {}", level),
+
+            let word = op(self.storage.word(0, p0), mask);
+            self.set_layer0(p0, word);
         }
     }
 
+    /// Adds the bits of `word` to the layer 0 word at offset `p0`,
+    /// growing the set and setting the summary layers as needed.
+    pub(crate) fn insert_word(&mut self, p0: usize, word: T) {
+        if word == T::ZERO {
+            return;
+        }
+        Self::valid_range((p0 << T::SHIFT1) as Index);
+        let old = self.storage.word(0, p0);
+        self.set_layer0(p0, old | word);
+    }
+
     /// Removes `id` from the set, returns `true` if the value
     /// was removed, and `false` if the value was not set
     /// to begin with.
     #[inline]
     pub fn remove(&mut self, id: Index) -> bool {
-        let (p0, p1, p2) = offsets::<T>(id);
-
-        if p0 >= self.layer0.len() {
-            return false;
-        }
-
-        if self.layer0[p0] & id.mask::<T>(T::SHIFT0) == T::ZERO {
+        let (p0, mask) = (id.offset(T::SHIFT1), id.mask::<T>(T::SHIFT0));
+        let old = self.storage.word(0, p0);
+        if old & mask == T::ZERO {
             return false;
         }
 
         // if the bitmask was set we need to clear
-        // its bit from layer0 to 3. the layers abover only
-        // should be cleared if the bit cleared was the last bit
+        // its bit from layer0 to 3. the storage only clears
+        // the layers above if the bit cleared was the last bit
         // in its set
-        self.layer0[p0] &= !id.mask::<T>(T::SHIFT0);
-        if self.layer0[p0] != T::ZERO {
-            return true;
-        }
-
-        self.layer1[p1] &= !id.mask::<T>(T::SHIFT1);
-        if self.layer1[p1] != T::ZERO {
-            return true;
-        }
+        self.storage.set_word(p0, old & !mask);
+        self.len -= 1;
+        true
+    }
 
-        self.layer2[p2] &= !id.mask::<T>(T::SHIFT2);
-        if self.layer2[p2] != T::ZERO {
-            return true;
+    /// Removes every index of `other` from the set, leaving the
+    /// difference of both sets.
+    ///
+    /// Only the layer 0 words present in both sets are visited, found by
+    /// walking the summary layers of `other` and of the set together.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let mut a: BitSet = (0..10).collect();
+    /// let b: BitSet = (5..20).collect();
+    /// a.remove_all(&b);
+    /// assert_eq!(a.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+    /// ```
+    pub fn remove_all<B>(&mut self, other: &B)
+    where
+        B: BitSetLike<L, Underlying = T>,
+    {
+        use iter::State::Continue;
+        let mut iter = other.iter();
+        iter.masks[L - 1] &= self.get_from_layer(L - 1, 0);
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = iter.prefix[lower] as usize >> T::LOG_BITS;
+            if lower == 0 {
+                let word = self.storage.word(0, idx) & !other.layer0(idx);
+                self.set_layer0(idx, word);
+            } else {
+                iter.masks[lower] &= self.get_from_layer(lower, idx);
+            }
         }
-
-        self.layer3 &= !id.mask::<T>(T::SHIFT3);
-        return true;
     }
 
     /// Returns `true` if `id` is in the set.
     #[inline]
     pub fn contains(&self, id: Index) -> bool {
-        let p0 = id.offset(T::SHIFT1);
-        p0 < self.layer0.len() && (self.layer0[p0] & id.mask::<T>(T::SHIFT0)) != T::ZERO
+        self.storage.word(0, id.offset(T::SHIFT1)) & id.mask::<T>(T::SHIFT0) != T::ZERO
     }
 
     /// Returns `true` if all ids in `other` are contained in this set
     #[inline]
-    pub fn contains_set<S>(&self, other: &S) -> bool
+    pub fn contains_set<B>(&self, other: &B) -> bool
     where
-        S: BitSetLike<Underlying = T>,
+        B: BitSetLike<L, Underlying = T>,
     {
-        for id in other.iter() {
-            if !self.contains(id) {
-                return false;
-            }
-        }
-        true
+        other.is_subset(self)
+    }
+
+    /// Returns the number of indices in the set.
+    ///
+    /// The count is maintained on every modification, so this is `O(1)`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the set contains no indices.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Replaces the layer 0 word at `idx` with `word`, keeping the cached
+    /// count, the summary layers and the full block summaries up to date.
+    #[inline]
+    fn set_layer0(&mut self, idx: usize, word: T) {
+        let old = self.storage.set_word(idx, word);
+        self.len = self.len + word.count_ones() as usize - old.count_ones() as usize;
     }
 
     /// Completely wipes out the bit set.
+    ///
+    /// The memory of the layers is kept, see [`shrink_to_fit`](#method.shrink_to_fit)
+    /// to release it.
     pub fn clear(&mut self) {
-        self.layer0.clear();
-        self.layer1.clear();
-        self.layer2.clear();
-        self.layer3 = T::ZERO;
+        self.storage.clear();
+        self.len = 0;
+    }
+
+    /// Returns the number of bytes allocated on the heap by the set.
+    ///
+    /// This counts the capacity of the layers, which can be larger than
+    /// what the indices of the set need.
+    pub fn heap_size(&self) -> usize {
+        self.storage.heap_size()
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> GenericBitSet<T, L> {
+    /// Shrinks the layers to only hold the words up to the highest index
+    /// of the set, releasing the rest of their memory.
+    ///
+    /// The highest index is found by walking down the summary layers, so
+    /// the trailing zero words aren't scanned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let mut set = BitSet::new();
+    /// set.add(1_000_000);
+    /// set.add(5);
+    /// let size = set.heap_size();
+    ///
+    /// set.remove(1_000_000);
+    /// set.shrink_to_fit();
+    /// assert!(set.heap_size() < size);
+    /// assert!(set.contains(5));
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        let last = BitSetLike::last(self);
+        for level in 0..L {
+            let len = last.map_or(0, |last| last.offset(T::LOG_BITS * (level + 1)) + 1);
+            let storage = &mut self.storage;
+            for layer in [&mut storage.layers[level], &mut storage.full[level]] {
+                layer.truncate(len);
+                layer.shrink_to_fit();
+            }
+        }
     }
 
     /// How many bits are in a `usize`.
@@ -298,7 +688,7 @@ impl<T: UnsignedInteger> GenericBitSet<T> {
     /// assert_eq!(slice[slice_index], 1 << bit_at_index);
     /// ```
     pub fn layer0_as_slice(&self) -> &[T] {
-        self.layer0.as_slice()
+        self.layer_as_slice(0)
     }
 
     /// How many `Index`es are described by as single layer 1 bit, intended for use with
@@ -344,7 +734,7 @@ impl<T: UnsignedInteger> GenericBitSet<T> {
     /// assert_eq!(slice[slice_index], 1 << bit_at_index);
     /// ```
     pub fn layer1_as_slice(&self) -> &[T] {
-        self.layer1.as_slice()
+        self.layer_as_slice(1)
     }
 
     /// How many `Index`es are described by as single layer 2 bit, intended for use with
@@ -389,7 +779,20 @@ impl<T: UnsignedInteger> GenericBitSet<T> {
     /// assert_eq!(slice[slice_index], 1 << bit_at_index);
     /// ```
     pub fn layer2_as_slice(&self) -> &[T] {
-        self.layer2.as_slice()
+        self.layer_as_slice(2)
+    }
+
+    /// Returns the layer `level` of the bitset as a slice, where layer 0 holds a bit per
+    /// `Index` and every other layer summarizes the one below it.
+    ///
+    /// The slice's length is not guaranteed, except that it will be at least the length needed to
+    /// reflect all the `1`s in the bitset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` isn't less than the number of layers.
+    pub fn layer_as_slice(&self, level: usize) -> &[T] {
+        self.storage.layers[level].as_slice()
     }
 }
 
@@ -401,132 +804,455 @@ impl<T: UnsignedInteger> GenericBitSet<T> {
 /// Layer0 each bit represents one Index of the set
 /// Layer1 each bit represents one `usize` of Layer0, and will be
 /// set only if the word below it is not zero.
-/// Layer2 has the same arrangement but with Layer1, and Layer3 with Layer2.
+/// Layer2 has the same arrangement but with Layer1, and so on up to
+/// the top layer `L - 1`, which is a single word.
 ///
 /// This arrangement allows for rapid jumps across the key-space.
 ///
+/// The number of layers `L` defaults to 4. Implementors provide
+/// [`get_from_layer`], and the `layer0` to `layer3` methods are shorthands
+/// for it.
+///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
-pub trait BitSetLike {
+/// [`get_from_layer`]: #method.get_from_layer
+pub trait BitSetLike<const L: usize = LAYERS> {
     /// Type of the underlying bit storage
     type Underlying: UnsignedInteger;
 
     /// Gets the `usize` corresponding to layer and index.
     ///
-    /// The `layer` should be in the range [0, 3]
-    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
-        match layer {
-            0 => self.layer0(idx),
-            1 => self.layer1(idx),
-            2 => self.layer2(idx),
-            3 => self.layer3(),
-            _ => panic!("Invalid layer: {}", layer),
+    /// The `layer` should be in the range [0, L - 1]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying;
+
+    /// Gets the word of the full block summary corresponding to layer and index.
+    ///
+    /// A bit of this summary is set only if every index below the matching
+    /// bit of [`get_from_layer`] is in the set, which lets [`BitSetNot`] skip
+    /// the full blocks. It may miss full blocks, but must never report one
+    /// that isn't, so by default only layer 0 is reported, where a block is
+    /// a single index.
+    ///
+    /// [`get_from_layer`]: #method.get_from_layer
+    /// [`BitSetNot`]: struct.BitSetNot.html
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        if layer == 0 {
+            self.get_from_layer(0, idx)
+        } else {
+            Self::Underlying::ZERO
+        }
+    }
+
+    /// Returns true if this `BitSetLike` contains nothing, and false otherwise.
+    fn is_empty(&self) -> bool {
+        self.get_from_layer(L - 1, 0) == Self::Underlying::ZERO
+    }
+
+    /// Returns the number of indices in the set.
+    ///
+    /// The default implementation only visits the layer 0 words whose
+    /// summary bits are set and counts their ones.
+    fn count(&self) -> usize {
+        use iter::State::Continue;
+        let mut count = 0;
+        let mut iter = self.iter();
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            if level == 1 {
+                count += iter.masks[0].count_ones() as usize;
+            }
+        }
+        count
+    }
+
+    /// Returns `true` if every index in this set is also in `other`.
+    ///
+    /// The words of both sets are compared layer by layer, from the top.
+    /// Full blocks of this set outside the summary of `other` end the walk
+    /// early, and full blocks of `other` aren't descended into.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let small: BitSet = [1, 64, 10_000].iter().collect();
+    /// let large: BitSet = (0..20_000).collect();
+    /// assert!(small.is_subset(&large));
+    /// assert!(!large.is_subset(&small));
+    /// ```
+    fn is_subset<S>(&self, other: &S) -> bool
+    where
+        S: BitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        use iter::State::Continue;
+        let zero = Self::Underlying::ZERO;
+        // Summary bits may be stale, so only full blocks, and layer 0 words,
+        // tell for sure that this set holds indices missing from `other`.
+        let check = |mask: &mut Self::Underlying, level: usize, idx: usize| {
+            let full = match level {
+                0 => *mask,
+                _ => self.get_full_from_layer(level, idx),
+            };
+            if full & !other.get_from_layer(level, idx) != zero {
+                return false;
+            }
+            *mask &= !other.get_full_from_layer(level, idx);
+            true
+        };
+        let mut iter = self.iter();
+        if !check(&mut iter.masks[L - 1], L - 1, 0) {
+            return false;
+        }
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = iter.prefix[lower] as usize >> Self::Underlying::LOG_BITS;
+            if !check(&mut iter.masks[lower], lower, idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns `true` if every index in `other` is also in this set.
+    fn is_superset<S>(&self, other: &S) -> bool
+    where
+        S: BitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if this set has no index in common with `other`.
+    ///
+    /// The summary layers of both sets are intersected first, so sets
+    /// covering different regions are told apart without reaching layer 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let low: BitSet = (0..1_000).collect();
+    /// let high: BitSet = (1_000..2_000).collect();
+    /// assert!(low.is_disjoint(&high));
+    /// assert!(low.intersects(&(&low | &high)));
+    /// ```
+    fn is_disjoint<S>(&self, other: &S) -> bool
+    where
+        S: BitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        BitSetAnd::<_, _, L>(self, other).first().is_none()
+    }
+
+    /// Copies the set into an owned `GenericBitSet`, see
+    /// [`GenericBitSet::from_bitset_like`].
+    ///
+    /// [`GenericBitSet::from_bitset_like`]: struct.GenericBitSet.html#method.from_bitset_like
+    fn to_bitset(&self) -> GenericBitSet<Self::Underlying, L> {
+        GenericBitSet::from_bitset_like(self)
+    }
+
+    /// Returns `true` if this set and `other` hold the same indices, whatever
+    /// their types.
+    ///
+    /// Only the layer 0 words below the summary bits of either set are compared.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{AtomicBitSet, BitSet, BitSetAnd, BitSetLike};
+    ///
+    /// let even: BitSet = (0..100).filter(|i| i % 2 == 0).collect();
+    /// let odd: BitSet = (0..100).filter(|i| i % 2 == 1).collect();
+    /// let atomic: AtomicBitSet = (0..100).collect();
+    /// assert!(atomic.set_eq(&(&even | &odd)));
+    /// assert!(BitSetAnd(&even, &odd).set_eq(&BitSet::new()));
+    /// ```
+    fn set_eq<S>(&self, other: &S) -> bool
+    where
+        S: BitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        BitSetXor::<_, _, L>(self, other).first().is_none()
+    }
+
+    /// Returns `true` if this set has at least one index in common with `other`.
+    fn intersects<S>(&self, other: &S) -> bool
+    where
+        S: BitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        !self.is_disjoint(other)
+    }
+
+    /// Returns the smallest index in the set, or `None` if the set is empty.
+    ///
+    /// This descends from layer 3 to layer 0 with a single probe per layer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = [700, 3, 100_000].iter().collect();
+    /// assert_eq!(set.first(), Some(3));
+    /// assert_eq!(BitSet::new().first(), None);
+    /// ```
+    fn first(&self) -> Option<Index> {
+        let bits = Self::Underlying::LOG_BITS;
+        let mut idx = 0;
+        for level in (0..L).rev() {
+            let word = self.get_from_layer(level, idx);
+            if word == Self::Underlying::ZERO {
+                // The summary layers of some sets (e.g. `BitSetAnd`) may have
+                // bits set without anything below them, so search past this word.
+                let next = (idx + 1) << (bits * (level + 1));
+                if level == L - 1 || next > Index::MAX as usize {
+                    return None;
+                }
+                return self.next_set(next as Index);
+            }
+            idx = (idx << bits) | word.trailing_zeros() as usize;
+        }
+        Some(idx as Index)
+    }
+
+    /// Returns the largest index in the set, or `None` if the set is empty.
+    ///
+    /// This descends from layer 3 to layer 0 with a single probe per layer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = [700, 3, 100_000].iter().collect();
+    /// assert_eq!(set.last(), Some(100_000));
+    /// assert_eq!(BitSet::new().last(), None);
+    /// ```
+    fn last(&self) -> Option<Index> {
+        let bits = Self::Underlying::LOG_BITS;
+        let row = (1 << bits) - 1;
+        let mut idx = 0;
+        for level in (0..L).rev() {
+            let word = self.get_from_layer(level, idx);
+            if word == Self::Underlying::ZERO {
+                // The summary layers of some sets (e.g. `BitSetAnd`) may have
+                // bits set without anything below them, so search before this word.
+                let start = idx << (bits * (level + 1));
+                if start == 0 {
+                    return None;
+                }
+                return self.prev_set((start - 1) as Index);
+            }
+            idx = (idx << bits) | (row - word.leading_zeros() as usize);
+        }
+        Some(idx as Index)
+    }
+
+    /// Returns the smallest index in the set that is greater than or equal to `from`.
+    ///
+    /// Empty regions are skipped using the summary layers, so this doesn't
+    /// scan layer 0 word by word.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = [3, 700, 100_000].iter().collect();
+    /// assert_eq!(set.next_set(0), Some(3));
+    /// assert_eq!(set.next_set(3), Some(3));
+    /// assert_eq!(set.next_set(4), Some(700));
+    /// assert_eq!(set.next_set(100_001), None);
+    /// ```
+    fn next_set(&self, from: Index) -> Option<Index> {
+        let bits = Self::Underlying::LOG_BITS;
+        let row = (1 << bits) - 1;
+        let capacity = capacity::<Self::Underlying>(L);
+        let (mut level, mut pos) = (0, from as usize);
+        loop {
+            if pos as u64 >= capacity.checked_shr((bits * level) as u32).unwrap_or(0) {
+                // past the end of the keyspace
+                return None;
+            }
+            let word = self.get_from_layer(level, pos >> bits)
+                & (Self::Underlying::MAX << Self::Underlying::from_u32((pos & row) as u32));
+            if word == Self::Underlying::ZERO {
+                // nothing left in this word, continue with the next one a layer up
+                pos = (pos >> bits) + 1;
+                level += 1;
+                continue;
+            }
+            pos = (pos & !row) | word.trailing_zeros() as usize;
+            if level == 0 {
+                return Some(pos as Index);
+            }
+            level -= 1;
+            pos <<= bits;
         }
     }
 
-    /// Returns true if this `BitSetLike` contains nothing, and false otherwise.
-    fn is_empty(&self) -> bool {
-        self.layer3() == Self::Underlying::ZERO
+    /// Returns the largest index in the set that is less than or equal to `from`.
+    ///
+    /// Empty regions are skipped using the summary layers, so this doesn't
+    /// scan layer 0 word by word.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = [3, 700, 100_000].iter().collect();
+    /// assert_eq!(set.prev_set(u32::MAX), Some(100_000));
+    /// assert_eq!(set.prev_set(700), Some(700));
+    /// assert_eq!(set.prev_set(699), Some(3));
+    /// assert_eq!(set.prev_set(2), None);
+    /// ```
+    fn prev_set(&self, from: Index) -> Option<Index> {
+        let bits = Self::Underlying::LOG_BITS;
+        let row = (1 << bits) - 1;
+        let last = (capacity::<Self::Underlying>(L) - 1) as usize;
+        let (mut level, mut pos) = (0, last.min(from as usize));
+        loop {
+            let word = self.get_from_layer(level, pos >> bits)
+                & (Self::Underlying::MAX >> Self::Underlying::from_u32((row - (pos & row)) as u32));
+            if word == Self::Underlying::ZERO {
+                if pos >> bits == 0 {
+                    // nothing before this word
+                    return None;
+                }
+                // continue with the previous word a layer up
+                pos = (pos >> bits) - 1;
+                level += 1;
+                continue;
+            }
+            pos = (pos & !row) | (row - word.leading_zeros() as usize);
+            if level == 0 {
+                return Some(pos as Index);
+            }
+            level -= 1;
+            pos = (pos << bits) | row;
+        }
     }
 
     /// Return a `usize` where each bit represents if any word in layer2
     /// has been set.
-    fn layer3(&self) -> Self::Underlying;
+    ///
+    /// This is the top layer of a set with 4 layers, and the first word
+    /// of layer 3 otherwise, or the top layer if there are fewer layers.
+    fn layer3(&self) -> Self::Underlying {
+        self.get_from_layer(3.min(L - 1), 0)
+    }
 
     /// Return the `usize` from the array of usizes that indicates if any
     /// bit has been set in layer1
-    fn layer2(&self, i: usize) -> Self::Underlying;
+    fn layer2(&self, i: usize) -> Self::Underlying {
+        self.get_from_layer(2, i)
+    }
 
     /// Return the `usize` from the array of usizes that indicates if any
     /// bit has been set in layer0
-    fn layer1(&self, i: usize) -> Self::Underlying;
+    fn layer1(&self, i: usize) -> Self::Underlying {
+        self.get_from_layer(1, i)
+    }
 
     /// Return a `usize` that maps to the direct 1:1 association with
     /// each index of the set
-    fn layer0(&self, i: usize) -> Self::Underlying;
+    fn layer0(&self, i: usize) -> Self::Underlying {
+        self.get_from_layer(0, i)
+    }
 
     /// Allows checking if set bit is contained in the bit set.
     fn contains(&self, i: Index) -> bool;
 
     /// Create an iterator that will scan over the keyspace
-    fn iter(self) -> BitIter<Self>
+    fn iter(self) -> BitIter<Self, L>
     where
         Self: Sized,
     {
-        let layer3 = self.layer3();
+        let mut masks = [Self::Underlying::ZERO; L];
+        masks[L - 1] = self.get_from_layer(L - 1, 0);
+
+        BitIter::new(self, masks, [0; L])
+    }
 
-        BitIter::new(
-            self,
-            [
-                Self::Underlying::ZERO,
-                Self::Underlying::ZERO,
-                Self::Underlying::ZERO,
-                layer3,
-            ],
-            [0; LAYERS - 1],
-        )
+    /// Create an iterator that will scan over the part of the keyspace within `range`
+    ///
+    /// The iterator starts at the beginning of the range instead of
+    /// skipping everything before it, and stops at the end of the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = (0..100_000).filter(|i| i % 1_000 == 0).collect();
+    /// let range = (&set).iter_range(10_000..20_000).collect::<Vec<_>>();
+    /// assert_eq!(range, (10..20).map(|i| i * 1_000).collect::<Vec<_>>());
+    /// ```
+    fn iter_range<R>(self, range: R) -> BitIter<Self, L>
+    where
+        Self: Sized,
+        R: RangeBounds<Index>,
+    {
+        BitIter::new_range(self, inclusive_range(range))
     }
 
     /// Create a parallel iterator that will scan over the keyspace
     #[cfg(feature = "parallel")]
-    fn par_iter(self) -> BitParIter<Self>
+    fn par_iter(self) -> BitParIter<Self, L>
     where
         Self: Sized,
     {
         BitParIter::new(self)
     }
+
+    /// Create a parallel iterator that will scan over the part of the keyspace within `range`
+    #[cfg(feature = "parallel")]
+    fn par_iter_range<R>(self, range: R) -> BitParIter<Self, L>
+    where
+        Self: Sized,
+        R: RangeBounds<Index>,
+    {
+        BitParIter::new(self).range(range)
+    }
 }
 
 /// A extension to the [`BitSetLike`] trait which allows draining it.
-pub trait DrainableBitSet: BitSetLike {
+pub trait DrainableBitSet<const L: usize = LAYERS>: BitSetLike<L> {
     /// Removes bit from the bit set.
     ///
     /// Returns `true` if removal happened and `false` otherwise.
     fn remove(&mut self, i: Index) -> bool;
 
     /// Create a draining iterator that will scan over the keyspace and clears it while doing so.
-    fn drain<'a>(&'a mut self) -> DrainBitIter<'a, Self>
+    fn drain<'a>(&'a mut self) -> DrainBitIter<'a, Self, L>
     where
         Self: Sized,
     {
-        let layer3 = self.layer3();
-
-        DrainBitIter::new(
-            self,
-            [
-                Self::Underlying::ZERO,
-                Self::Underlying::ZERO,
-                Self::Underlying::ZERO,
-                layer3,
-            ],
-            [0; LAYERS - 1],
-        )
+        let mut masks = [Self::Underlying::ZERO; L];
+        masks[L - 1] = self.get_from_layer(L - 1, 0);
+
+        DrainBitIter::new(self, masks, [0; L])
     }
 }
 
-impl<'a, T> BitSetLike for &'a T
+impl<T, const L: usize> BitSetLike<L> for &T
 where
-    T: BitSetLike + ?Sized,
+    T: BitSetLike<L> + ?Sized,
 {
     type Underlying = T::Underlying;
 
     #[inline]
-    fn layer3(&self) -> T::Underlying {
-        (*self).layer3()
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (*self).get_from_layer(layer, idx)
     }
 
     #[inline]
-    fn layer2(&self, i: usize) -> T::Underlying {
-        (*self).layer2(i)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (*self).get_full_from_layer(layer, idx)
     }
 
     #[inline]
-    fn layer1(&self, i: usize) -> T::Underlying {
-        (*self).layer1(i)
-    }
-
-    #[inline]
-    fn layer0(&self, i: usize) -> T::Underlying {
-        (*self).layer0(i)
+    fn count(&self) -> usize {
+        (*self).count()
     }
 
     #[inline]
@@ -535,30 +1261,25 @@ where
     }
 }
 
-impl<'a, T> BitSetLike for &'a mut T
+impl<T, const L: usize> BitSetLike<L> for &mut T
 where
-    T: BitSetLike + ?Sized,
+    T: BitSetLike<L> + ?Sized,
 {
     type Underlying = T::Underlying;
 
     #[inline]
-    fn layer3(&self) -> T::Underlying {
-        (**self).layer3()
-    }
-
-    #[inline]
-    fn layer2(&self, i: usize) -> T::Underlying {
-        (**self).layer2(i)
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (**self).get_from_layer(layer, idx)
     }
 
     #[inline]
-    fn layer1(&self, i: usize) -> T::Underlying {
-        (**self).layer1(i)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (**self).get_full_from_layer(layer, idx)
     }
 
     #[inline]
-    fn layer0(&self, i: usize) -> T::Underlying {
-        (**self).layer0(i)
+    fn count(&self) -> usize {
+        (**self).count()
     }
 
     #[inline]
@@ -567,9 +1288,9 @@ where
     }
 }
 
-impl<'a, T> DrainableBitSet for &'a mut T
+impl<T, const L: usize> DrainableBitSet<L> for &mut T
 where
-    T: DrainableBitSet,
+    T: DrainableBitSet<L>,
 {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
@@ -577,27 +1298,29 @@ where
     }
 }
 
-impl<T: UnsignedInteger> BitSetLike for GenericBitSet<T> {
+impl<T, const L: usize, S> BitSetLike<L> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     type Underlying = T;
 
     #[inline]
-    fn layer3(&self) -> T {
-        self.layer3
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.storage.word(layer, idx)
     }
 
     #[inline]
-    fn layer2(&self, i: usize) -> T {
-        self.layer2.get(i).map(|&x| x).unwrap_or(T::ZERO)
-    }
-
-    #[inline]
-    fn layer1(&self, i: usize) -> T {
-        self.layer1.get(i).map(|&x| x).unwrap_or(T::ZERO)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        match layer {
+            0 => self.storage.word(0, idx),
+            _ => self.storage.full(layer, idx),
+        }
     }
 
     #[inline]
-    fn layer0(&self, i: usize) -> T {
-        self.layer0.get(i).map(|&x| x).unwrap_or(T::ZERO)
+    fn count(&self) -> usize {
+        self.len
     }
 
     #[inline]
@@ -606,55 +1329,92 @@ impl<T: UnsignedInteger> BitSetLike for GenericBitSet<T> {
     }
 }
 
-impl<T: UnsignedInteger> DrainableBitSet for GenericBitSet<T> {
+impl<T, const L: usize, S> DrainableBitSet<L> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
         self.remove(i)
     }
 }
 
-impl<T: UnsignedInteger> PartialEq for GenericBitSet<T> {
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> PartialEq for GenericBitSet<T, L, S> {
+    /// Two sets are equal when they hold the same indices, whatever
+    /// memory they have allocated.
     #[inline]
-    fn eq(&self, rhv: &GenericBitSet<T>) -> bool {
-        if self.layer3 != rhv.layer3 {
-            return false;
-        }
-        if self.layer2.len() != rhv.layer2.len()
-            || self.layer1.len() != rhv.layer1.len()
-            || self.layer0.len() != rhv.layer0.len()
-        {
-            return false;
-        }
+    fn eq(&self, rhv: &GenericBitSet<T, L, S>) -> bool {
+        self.len == rhv.len && WordIter::<_, L>::new(self).eq(WordIter::new(rhv))
+    }
+}
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> Eq for GenericBitSet<T, L, S> {}
 
-        for i in 0..self.layer2.len() {
-            if self.layer2(i) != rhv.layer2(i) {
-                return false;
-            }
-        }
-        for i in 0..self.layer1.len() {
-            if self.layer1(i) != rhv.layer1(i) {
-                return false;
-            }
+impl<T, const L: usize, S> Hash for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger + Hash,
+    S: Storage<T, L>,
+{
+    /// Hashes the non-zero layer 0 words along with their offsets, so the
+    /// hash doesn't depend on the allocated memory either.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for word in WordIter::<_, L>::new(self) {
+            word.hash(state);
         }
-        for i in 0..self.layer0.len() {
-            if self.layer0(i) != rhv.layer0(i) {
-                return false;
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> PartialOrd for GenericBitSet<T, L, S> {
+    #[inline]
+    fn partial_cmp(&self, rhv: &GenericBitSet<T, L, S>) -> Option<Ordering> {
+        Some(self.cmp(rhv))
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize, S: Storage<T, L>> Ord for GenericBitSet<T, L, S> {
+    /// Sets are ordered lexicographically by their indices in increasing
+    /// order, like a `BTreeSet<Index>`.
+    fn cmp(&self, rhv: &GenericBitSet<T, L, S>) -> Ordering {
+        // The sets agree up to the smallest index held by only one of them.
+        // That set is the smaller one, unless the other has no larger index.
+        let first = match BitSetXor::<_, _, L>(self, rhv).first() {
+            Some(first) => first,
+            None => return Ordering::Equal,
+        };
+        let has_more = |set: &Self| {
+            first
+                .checked_add(1)
+                .and_then(|next| set.next_set(next))
+                .is_some()
+        };
+        if self.contains(first) {
+            if has_more(rhv) {
+                Ordering::Less
+            } else {
+                Ordering::Greater
             }
+        } else if has_more(self) {
+            Ordering::Greater
+        } else {
+            Ordering::Less
         }
-
-        true
     }
 }
-impl<T: UnsignedInteger> Eq for GenericBitSet<T> {}
 
 #[cfg(test)]
 mod tests {
     extern crate typed_test_gen;
     use self::typed_test_gen::test_with;
 
-    use super::{BitSetAnd, BitSetLike, BitSetNot, GenericBitSet, UnsignedInteger};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
 
-    #[test_with(u32, u64, usize)]
+    use super::{
+        AtomicBitSet, BitSetAnd, BitSetLike, BitSetNot, BitSetOr, BitSetXor, CapacityError,
+        DrainableBitSet, GenericBitSet, Index, Row, Storage, UnsignedInteger,
+    };
+
+    #[test_with(u32, u64, usize, u128)]
     fn insert<T: UnsignedInteger>() {
         let mut c = GenericBitSet::<T>::new();
         for i in 0..1_000 {
@@ -667,7 +1427,7 @@ mod tests {
         }
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn insert_100k<T: UnsignedInteger>() {
         let mut c = GenericBitSet::<T>::new();
         for i in 0..100_000 {
@@ -680,7 +1440,7 @@ mod tests {
         }
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn remove<T: UnsignedInteger>() {
         let mut c = GenericBitSet::<T>::new();
         for i in 0..1_000 {
@@ -695,7 +1455,7 @@ mod tests {
         }
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iter<T: UnsignedInteger>() {
         let mut c = GenericBitSet::<T>::new();
         for i in 0..100_000 {
@@ -710,7 +1470,7 @@ mod tests {
         assert_eq!(count, 100_000);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iter_odd_even<T: UnsignedInteger>() {
         let mut odd = GenericBitSet::<T>::new();
         let mut even = GenericBitSet::<T>::new();
@@ -727,7 +1487,7 @@ mod tests {
         assert_eq!(BitSetAnd(&odd, &even).iter().count(), 0);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iter_random_add<T: UnsignedInteger>() {
         use rand::prelude::*;
 
@@ -744,7 +1504,7 @@ mod tests {
         assert_eq!(set.iter().count(), added as usize);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn iter_clusters<T: UnsignedInteger>() {
         let mut set = GenericBitSet::<T>::new();
         for x in 0..8 {
@@ -760,7 +1520,327 @@ mod tests {
         assert_eq!(set.iter().count(), 8usize.pow(3));
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
+    fn len<T: UnsignedInteger>() {
+        let mut c = GenericBitSet::<T>::new();
+        assert_eq!(c.len(), 0);
+        for i in 0..1_000 {
+            c.add(i * 3);
+            c.add(i * 3);
+        }
+        assert_eq!(c.len(), 1_000);
+        for i in 0..500 {
+            c.remove(i * 3);
+            c.remove(i * 3 + 1);
+        }
+        assert_eq!(c.len(), 500);
+        Extend::extend(&mut c, 0..10);
+        assert_eq!(c.len(), 510);
+        assert_eq!(c.len(), (&c).iter().count());
+        c.clear();
+        assert_eq!(c.len(), 0);
+        assert!(c.is_empty());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn count<T: UnsignedInteger>() {
+        let mut odd = GenericBitSet::<T>::new();
+        let mut threes = GenericBitSet::<T>::new();
+        for i in 0..100_000 {
+            if i % 2 == 1 {
+                odd.add(i);
+            }
+            if i % 3 == 0 {
+                threes.add(i);
+            }
+        }
+
+        assert_eq!(BitSetLike::count(&odd), 50_000);
+        assert_eq!(BitSetAnd(&odd, &threes).count(), 16_667);
+        assert_eq!(
+            BitSetAnd(&odd, &threes).count(),
+            BitSetAnd(&odd, &threes).iter().count()
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn next_prev_set<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let mut set = GenericBitSet::<T>::new();
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for _ in 0..1_000 {
+            set.add(rng.gen_range(0, limit));
+        }
+        let members = (&set).iter().collect::<Vec<_>>();
+
+        for _ in 0..1_000 {
+            let from = rng.gen_range(0, limit + 1_000);
+            let next = members.iter().cloned().find(|&i| i >= from);
+            let prev = members.iter().cloned().rev().find(|&i| i <= from);
+            assert_eq!(set.next_set(from), next, "next_set({})", from);
+            assert_eq!(set.prev_set(from), prev, "prev_set({})", from);
+        }
+        for &i in &members {
+            assert_eq!(set.next_set(i), Some(i));
+            assert_eq!(set.prev_set(i), Some(i));
+        }
+
+        assert_eq!(GenericBitSet::<T>::new().next_set(0), None);
+        assert_eq!(GenericBitSet::<T>::new().prev_set(Index::MAX), None);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn next_prev_set_combinators<T: UnsignedInteger>() {
+        let mut a = GenericBitSet::<T>::new();
+        let mut b = GenericBitSet::<T>::new();
+        // `a` and `b` share summary bits, but no layer 0 bits below 100_000
+        for i in 0..1_000 {
+            a.add(i * 2);
+            b.add(i * 2 + 1);
+        }
+        a.add(100_000);
+        b.add(100_000);
+
+        let and = BitSetAnd(&a, &b);
+        assert_eq!(and.next_set(0), Some(100_000));
+        assert_eq!(and.prev_set(99_999), None);
+        assert_eq!(and.prev_set(100_000), Some(100_000));
+
+        let not = BitSetNot(&a);
+        assert_eq!(not.next_set(0), Some(1));
+        assert_eq!(not.next_set(2_000), Some(2_000));
+        assert_eq!(not.prev_set(1_998), Some(1_997));
+        assert_eq!(not.prev_set(0), None);
+        assert_eq!(not.next_set(T::MAX_EID - 1), Some(T::MAX_EID - 1));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn first_last<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let mut set = GenericBitSet::<T>::new();
+        assert_eq!(set.first(), None);
+        assert_eq!(set.last(), None);
+
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for _ in 0..100 {
+            set.add(rng.gen_range(0, limit));
+        }
+        let members = (&set).iter().collect::<Vec<_>>();
+        assert_eq!(set.first(), members.first().cloned());
+        assert_eq!(set.last(), members.last().cloned());
+
+        set.add(0);
+        set.add(T::MAX_EID - 1);
+        assert_eq!(set.first(), Some(0));
+        assert_eq!(set.last(), Some(T::MAX_EID - 1));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn first_last_and<T: UnsignedInteger>() {
+        let mut a = GenericBitSet::<T>::new();
+        let mut b = GenericBitSet::<T>::new();
+        // `a` and `b` share summary bits, but only some layer 0 bits
+        for i in 0..1_000 {
+            a.add(i * 2);
+            b.add(i * 2 + 1);
+        }
+        assert_eq!(BitSetAnd(&a, &b).first(), None);
+        assert_eq!(BitSetAnd(&a, &b).last(), None);
+
+        a.add(501);
+        a.add(1_001);
+        assert_eq!(BitSetAnd(&a, &b).first(), Some(501));
+        assert_eq!(BitSetAnd(&a, &b).last(), Some(1_001));
+        assert_eq!(BitSetNot(&a).first(), Some(1));
+        assert_eq!(BitSetNot(&a).last(), Some(T::MAX_EID - 1));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn range_updates<T: UnsignedInteger>() {
+        use rand::prelude::*;
+        use std::collections::BTreeSet;
+
+        let mut set = GenericBitSet::<T>::new();
+        let mut check = BTreeSet::new();
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for round in 0..150 {
+            let a = rng.gen_range(0, limit);
+            let b = rng.gen_range(a, (a + 5_000).min(limit));
+            match round % 3 {
+                0 => {
+                    set.insert_range(a..b);
+                    check.extend(a..b);
+                }
+                1 => {
+                    set.remove_range(a..b);
+                    for i in a..b {
+                        check.remove(&i);
+                    }
+                }
+                _ => {
+                    set.toggle_range(a..b);
+                    for i in a..b {
+                        if !check.remove(&i) {
+                            check.insert(i);
+                        }
+                    }
+                }
+            }
+            assert_eq!(set.len(), check.len());
+        }
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            check.iter().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(BitSetLike::count(&BitSetAnd(&set, &set)), check.len());
+        assert_full_summaries(&set);
+
+        set.remove_range(..);
+        assert!(set.is_empty());
+        assert_eq!(set.layer3(), T::ZERO);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn range_updates_edges<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        set.remove_range(10..20);
+        set.insert_range(5..5);
+        assert!(set.is_empty());
+
+        let word = T::BITS as Index;
+        set.insert_range(word - 1..=word);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [word - 1, word]);
+        set.toggle_range(0..word * 2);
+        assert_eq!(set.len(), word as usize * 2 - 2);
+        assert!(!set.contains(word - 1) && !set.contains(word));
+        set.remove_range(1..);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [0]);
+
+        set.insert_range(T::MAX_EID - 3..T::MAX_EID);
+        assert_eq!(set.last(), Some(T::MAX_EID - 1));
+        assert_eq!(set.len(), 4);
+
+        // Unbounded ends stop at the last index the set can hold.
+        let mut set = GenericBitSet::<T, 2>::new();
+        let max = GenericBitSet::<T, 2>::CAPACITY as Index - 1;
+        set.insert_range(max - 10..);
+        assert_eq!(
+            (set.first(), set.last(), set.len()),
+            (Some(max - 10), Some(max), 11)
+        );
+        set.toggle_range(..);
+        assert_eq!(
+            (set.first(), set.last(), set.len()),
+            (Some(0), Some(max - 11), max as usize - 10)
+        );
+        set.insert_range(..);
+        assert_eq!(set.len(), max as usize + 1);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn subset_disjoint<T: UnsignedInteger>() {
+        let mut even = GenericBitSet::<T>::new();
+        let mut odd = GenericBitSet::<T>::new();
+        let mut fours = GenericBitSet::<T>::new();
+        for i in 0..10_000 {
+            if i % 2 == 0 {
+                even.add(i);
+            } else {
+                odd.add(i);
+            }
+            if i % 4 == 0 {
+                fours.add(i);
+            }
+        }
+        let empty = GenericBitSet::<T>::new();
+
+        assert!(fours.is_subset(&even));
+        assert!(!even.is_subset(&fours));
+        assert!(even.is_superset(&fours));
+        assert!(!fours.is_subset(&odd));
+        assert!(empty.is_subset(&odd));
+        assert!(even.is_subset(&even));
+        assert!(even.contains_set(&fours));
+        assert!(!fours.contains_set(&even));
+
+        assert!(even.is_disjoint(&odd));
+        assert!(!even.intersects(&odd));
+        assert!(fours.intersects(&even));
+        assert!(empty.is_disjoint(&even));
+
+        // summary layers overlap, layer 0 doesn't
+        let and = BitSetAnd(&even, &odd);
+        assert!(and.is_disjoint(&fours));
+        assert!(and.is_subset(&empty));
+        assert!(BitSetNot(&odd).is_superset(&fours));
+        assert!(BitSetOr(&even, &odd).is_superset(&fours));
+
+        odd.add(20_000);
+        fours.add(20_000);
+        assert!(!fours.is_subset(&even));
+        assert!(odd.intersects(&fours));
+
+        // full blocks on either side
+        let mut low = GenericBitSet::<T>::new();
+        low.insert_range(0..100_000);
+        let mut high = GenericBitSet::<T>::new();
+        high.insert_range(50_000..200_000);
+        assert!(!low.is_subset(&high));
+        assert!(!high.is_subset(&low));
+        assert!(even.is_subset(&low));
+        odd.add(150_000);
+        assert!(!odd.is_subset(&low));
+        high.insert_range(..50_000);
+        assert!(low.is_subset(&high));
+        assert!(BitSetNot(&BitSetNot(&low)).is_subset(&high));
+
+        // `&=` with a set whose summary bits have no indices below them
+        let mut stale = low.clone();
+        stale &= &BitSetAnd(&even, &BitSetNot(&even));
+        assert!(stale.is_subset(&empty));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn try_add<T: UnsignedInteger>() {
+        let mut c = GenericBitSet::<T>::new();
+        assert_eq!(c.try_add(0), Ok(false));
+        assert_eq!(c.try_add(0), Ok(true));
+        assert_eq!(c.try_add(T::MAX_EID - 1), Ok(false));
+        assert_eq!(
+            c.try_add(T::MAX_EID),
+            Err(CapacityError::OutOfRange {
+                index: T::MAX_EID,
+                max: T::MAX_EID,
+            })
+        );
+        assert_eq!(c.len(), 2);
+
+        assert!(c.try_extend(vec![1, 2, Index::MAX, 3]).is_err());
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [0, 1, 2, T::MAX_EID - 1]);
+
+        assert!(GenericBitSet::<T>::try_with_capacity(T::MAX_EID + 1).is_err());
+        let c = GenericBitSet::<T>::with_capacity(T::MAX_EID);
+        assert!(c.layer0_as_slice().len() * T::BITS >= T::MAX_EID as usize);
+        let c = GenericBitSet::<T>::try_with_capacity(1_000).unwrap();
+        assert!(c.layer0_as_slice().len() * T::BITS > 1_000);
+
+        // `|=` grows the layers but not the full block summaries, which
+        // `try_add` then reserves before filling a word.
+        let mut c = GenericBitSet::<T>::new();
+        c |= &[100_000].iter().cloned().collect::<GenericBitSet<T>>();
+        for i in 0..T::BITS as Index {
+            assert_eq!(c.try_add(i), Ok(false));
+        }
+        assert_full_summaries(&c);
+        assert_eq!(c.len(), T::BITS + 1);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
     fn not<T: UnsignedInteger>() {
         let mut c = GenericBitSet::<T>::new();
         for i in 0..10_000 {
@@ -773,6 +1853,393 @@ mod tests {
             assert_eq!(idx * 2, i as usize);
         }
     }
+
+    /// Checks the full block summaries of `set` against its layer 0.
+    fn assert_full_summaries<T, const L: usize, S>(set: &GenericBitSet<T, L, S>)
+    where
+        T: UnsignedInteger,
+        S: Storage<T, L>,
+    {
+        let len = set.last().map_or(0, |last| last.offset(T::SHIFT1) + 1);
+        let mut full: Vec<bool> = (0..len).map(|idx| set.layer0(idx) == T::MAX).collect();
+        for level in 1..L {
+            for (idx, blocks) in full.chunks(T::BITS).enumerate() {
+                let word = blocks
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &full)| full)
+                    .fold(T::ZERO, |word, (bit, _)| {
+                        word | T::ONE << T::from_u32(bit as u32)
+                    });
+                assert_eq!(set.get_full_from_layer(level, idx), word);
+            }
+            full = full
+                .chunks(T::BITS)
+                .map(|blocks| blocks.len() == T::BITS && blocks.iter().all(|&full| full))
+                .collect();
+        }
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn not_skips_full_blocks<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut set = GenericBitSet::<T>::new();
+        set.insert_range(0..3 * block + 5);
+        assert_full_summaries(&set);
+        assert_eq!(BitSetNot(&set).get_from_layer(1, 0), T::ZERO);
+        assert_eq!(BitSetNot(&set).get_from_layer(1, 2), T::ZERO);
+        assert_eq!(BitSetNot(&set).first(), Some(3 * block + 5));
+        assert_eq!(BitSetNot(&BitSetNot(&set)).last(), Some(3 * block + 4));
+
+        set.remove(block + 7);
+        assert_full_summaries(&set);
+        assert_eq!(BitSetNot(&set).first(), Some(block + 7));
+        assert!(BitSetNot(&BitSetNot(&set)).iter().eq((&set).iter()));
+
+        set.add(block + 7);
+        set.toggle_range(2 * block..2 * block + 3);
+        assert_full_summaries(&set);
+        assert_eq!(
+            BitSetNot(&set).iter().take(4).collect::<Vec<_>>(),
+            vec![2 * block, 2 * block + 1, 2 * block + 2, 3 * block + 5]
+        );
+
+        let all = BitSetNot(GenericBitSet::<T>::new());
+        assert!(BitSetNot(&all).is_empty());
+        assert_eq!(BitSetNot(BitSetXor(&set, &all)).first(), Some(0));
+        assert_eq!(BitSetNot(BitSetXor(&set, &all)).iter().count(), set.len());
+
+        set.clear();
+        assert_full_summaries(&set);
+        assert_eq!(BitSetNot(&set).first(), Some(0));
+    }
+
+    fn hash_of<H: Hash>(value: &H) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn eq_ignores_capacity<T: UnsignedInteger + Hash>() {
+        let mut small = GenericBitSet::<T>::new();
+        let mut large = GenericBitSet::<T>::with_capacity(100_000);
+        assert_eq!(small, large);
+        assert_eq!(hash_of(&small), hash_of(&large));
+
+        for &i in &[3, 64, 5_000] {
+            small.add(i);
+            large.add(i);
+        }
+        large.add(90_000);
+        assert_ne!(small, large);
+        large.remove(90_000);
+        assert_eq!(small, large);
+        assert_eq!(hash_of(&small), hash_of(&large));
+
+        small.add(4);
+        assert_ne!(small, large);
+        assert_ne!(hash_of(&small), hash_of(&large));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn ord<T: UnsignedInteger>() {
+        let sets: Vec<GenericBitSet<T>> = vec![
+            GenericBitSet::new(),
+            [0].iter().collect(),
+            [0, 1].iter().collect(),
+            [0, 1, 70_000].iter().collect(),
+            [0, 2].iter().collect(),
+            [0, 64].iter().collect(),
+            [1].iter().collect(),
+            [1, 100_000].iter().collect(),
+            [5_000].iter().collect(),
+            [T::MAX_EID - 1].iter().collect(),
+        ];
+        for a in &sets {
+            for b in &sets {
+                assert_eq!(a.cmp(b), a.iter().cmp(b.iter()), "{:?} {:?}", a, b);
+                assert_eq!(a == b, a.cmp(b).is_eq());
+            }
+        }
+        let mut sorted = sets.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert_eq!(sorted, sets);
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn set_eq<T: UnsignedInteger>() {
+        let set: GenericBitSet<T> = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let other: GenericBitSet<T> = (0..10_000).filter(|i| i % 5 == 0).collect();
+        let both: GenericBitSet<T> = (0..10_000).filter(|i| i % 15 == 0).collect();
+        assert!(BitSetAnd(&set, &other).set_eq(&both));
+        assert!(both.set_eq(&BitSetAnd(&set, &other)));
+        assert!(!set.set_eq(&both));
+        assert!(!both.set_eq(&set));
+        assert!(GenericBitSet::<T>::new().set_eq(&BitSetAnd(&both, &BitSetNot(&set))));
+    }
+
+    #[test]
+    fn set_eq_atomic() {
+        let atomic: AtomicBitSet = (0..10_000).filter(|i| i % 15 == 0).collect();
+        let set: GenericBitSet<usize> = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let other: GenericBitSet<usize> = (0..10_000).filter(|i| i % 5 == 0).collect();
+        assert!(atomic.set_eq(&BitSetAnd(&set, &other)));
+        assert!(!atomic.set_eq(&set));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn to_bitset<T: UnsignedInteger>() {
+        let a: GenericBitSet<T> = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let b: GenericBitSet<T> = (50_000..150_000).filter(|i| i % 2 == 0).collect();
+        let c: GenericBitSet<T> = (0..200_000).filter(|i| i % 5 == 0).collect();
+        let lazy = BitSetAnd(BitSetOr(&a, &b), BitSetNot(&c));
+
+        let copy = lazy.to_bitset();
+        let collected: GenericBitSet<T> = (&lazy).iter().collect();
+        assert_eq!(copy, collected);
+        assert_eq!(copy.len(), collected.len());
+        // The summary layers are rebuilt from the copied words only.
+        for level in 0..4 {
+            assert_eq!(copy.layer_as_slice(level), collected.layer_as_slice(level));
+        }
+
+        let empty = BitSetAnd(&a, BitSetNot(&a)).to_bitset();
+        assert!(empty.is_empty());
+        assert_eq!(empty.heap_size(), 0);
+        assert_eq!(GenericBitSet::from_bitset_like(&a), a);
+    }
+
+    #[test]
+    fn atomic_to_bitset() {
+        let atomic: AtomicBitSet = (0..10_000).filter(|i| i % 7 == 0).collect();
+        let set = atomic.to_bitset();
+        assert!(set.set_eq(&atomic));
+        assert_eq!(set.len(), (&atomic).iter().count());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn reserve<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        assert_eq!(set.capacity(), 0);
+        set.reserve(100_000);
+        let capacity = set.capacity();
+        assert!(capacity > 100_000);
+        assert!(set.is_empty());
+
+        let size = set.heap_size();
+        for i in (0..100_000).step_by(7) {
+            set.add(i);
+        }
+        assert_eq!(set.heap_size(), size);
+        assert_eq!(set.capacity(), capacity);
+
+        assert_eq!(set.try_reserve(10), Ok(()));
+        assert_eq!(set.capacity(), capacity);
+        assert_eq!(
+            set.try_reserve(T::MAX_EID),
+            Err(CapacityError::OutOfRange {
+                index: T::MAX_EID,
+                max: T::MAX_EID
+            })
+        );
+        set.reserve(T::MAX_EID - 1);
+        assert_eq!(set.capacity(), T::MAX_EID as usize);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn reserve_full_blocks<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        set.try_reserve(100_000).unwrap();
+        let size = set.heap_size();
+        // Filling whole words grows the full block summaries.
+        set.insert_range(..=100_000);
+        assert_eq!(set.heap_size(), size);
+        assert!(set.capacity() > 100_000);
+
+        let mut set = GenericBitSet::<T>::new();
+        set.try_reserve(100_000).unwrap();
+        for i in 0..=100_000 {
+            set.add(i);
+        }
+        assert_eq!(set.heap_size(), size);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected index to be less then 1048576, found 1048576")]
+    fn reserve_out_of_range() {
+        GenericBitSet::<u32>::new().reserve(1 << 20);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn shrink_to_fit<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        assert_eq!(set.heap_size(), 0);
+        set.add(T::MAX_EID - 1);
+        set.add(100);
+        let size = set.heap_size();
+        assert!(size >= (T::MAX_EID as usize - 1) / 8);
+
+        set.remove(T::MAX_EID - 1);
+        set.shrink_to_fit();
+        assert!(set.heap_size() < size);
+        assert_eq!(set.layer0_as_slice().len(), (100 >> T::LOG_BITS) + 1);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [100]);
+        set.add(T::MAX_EID - 1);
+        assert_eq!(set.last(), Some(T::MAX_EID - 1));
+
+        set.clear();
+        assert!(set.heap_size() > 0);
+        set.shrink_to_fit();
+        assert_eq!(set.heap_size(), 0);
+        assert!(set.is_empty());
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn more_layers<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T, 5>::new();
+        let ids = [5, 70_000, T::MAX_EID + 7, 30_000_000];
+        for &id in &ids {
+            assert!(!set.add(id));
+        }
+        assert!(set.contains(T::MAX_EID + 7));
+        assert!(!set.contains(T::MAX_EID));
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.count(), 4);
+        assert_eq!(set.first(), Some(5));
+        assert_eq!(set.last(), Some(30_000_000));
+        assert_eq!(set.next_set(70_001), Some(T::MAX_EID + 7));
+        assert_eq!(set.prev_set(29_999_999), Some(T::MAX_EID + 7));
+
+        let mut sorted = ids.to_vec();
+        sorted.sort();
+        assert_eq!((&set).iter().collect::<Vec<_>>(), sorted);
+        assert_eq!(
+            (&set).iter().rev().collect::<Vec<_>>(),
+            sorted.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (&set).iter_range(6..=T::MAX_EID + 7).collect::<Vec<_>>(),
+            [70_000, T::MAX_EID + 7]
+        );
+
+        let other: GenericBitSet<T, 5> = [5, 30_000_000, 32_000_000].iter().collect();
+        assert_eq!((&set & &other).iter().collect::<Vec<_>>(), [5, 30_000_000]);
+        assert_eq!((&set | &other).iter().count(), 5);
+        assert_eq!((&set ^ &other).iter().count(), 3);
+        assert_eq!((!&set).first(), Some(0));
+        assert_eq!((!&set).next_set(5), Some(6));
+
+        let mut union = set.clone();
+        union |= &other;
+        union &= &set;
+        assert_eq!(
+            (&union).iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+
+        assert!(set.remove(T::MAX_EID + 7));
+        assert_eq!(set.drain().collect::<Vec<_>>(), [5, 70_000, 30_000_000]);
+        assert!(set.is_empty());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn more_layers_capacity<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T, 3>::new();
+        let max = T::MAX_EID >> T::LOG_BITS;
+        assert_eq!(set.try_add(max - 1), Ok(false));
+        assert_eq!(
+            set.try_add(max),
+            Err(CapacityError::OutOfRange { index: max, max })
+        );
+        assert_eq!(BitSetNot(&set).last(), Some(max - 2));
+        // Without a layer 3, `layer3` is the top layer.
+        assert_eq!(set.layer3(), set.get_from_layer(2, 0));
+        assert_ne!(set.layer3(), T::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected index to be less then 1024, found 1024")]
+    fn add_capacity() {
+        let mut set = GenericBitSet::<u32, 2>::new();
+        set.add(1023);
+        set.add(GenericBitSet::<u32, 2>::CAPACITY as Index);
+    }
+
+    fn all_indices<T: UnsignedInteger, const L: usize>() {
+        // The top layer is masked to not go beyond the last index.
+        let empty = GenericBitSet::<T, L>::new();
+        assert_eq!(BitSetNot(&empty).last(), Some(Index::MAX));
+        assert_eq!(BitSetNot(&empty).next_set(Index::MAX), Some(Index::MAX));
+        assert_eq!(
+            BitSetNot(&empty)
+                .iter_range(Index::MAX - 10..)
+                .rev()
+                .collect::<Vec<_>>(),
+            (Index::MAX - 10..=Index::MAX).rev().collect::<Vec<_>>()
+        );
+        assert!(GenericBitSet::<T, L>::try_valid_range(Index::MAX).is_ok());
+    }
+
+    #[test]
+    fn all_indices_u32() {
+        all_indices::<u32, 7>();
+    }
+
+    #[test]
+    fn all_indices_u64() {
+        all_indices::<u64, 6>();
+    }
+
+    #[test]
+    fn all_indices_u128() {
+        all_indices::<u128, 5>();
+    }
+
+    #[test]
+    fn all_indices_small_words() {
+        all_indices::<u8, 11>();
+        all_indices::<u16, 8>();
+    }
+
+    #[test_with(u8, u16)]
+    fn small_words<T: UnsignedInteger>() {
+        let mut set = GenericBitSet::<T>::new();
+        let max = T::MAX_EID - 1;
+        let ids = [0, 7, 8, 255, 256, max];
+        for &id in &ids {
+            assert!(!set.add(id));
+            assert!(set.add(id));
+        }
+        assert_eq!(
+            set.try_add(max + 1),
+            Err(CapacityError::OutOfRange {
+                index: max + 1,
+                max: T::MAX_EID
+            })
+        );
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.count(), 6);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), ids);
+        assert_eq!((&set).iter().next_back(), Some(max));
+        assert_eq!(set.next_set(9), Some(255));
+        assert_eq!(set.prev_set(254), Some(8));
+
+        let other: GenericBitSet<T> = [7, 100, max].iter().collect();
+        assert_eq!((&set & &other).iter().collect::<Vec<_>>(), [7, max]);
+        assert_eq!((&set ^ &other).iter().count(), 5);
+        assert_eq!(BitSetNot(&set).iter().count() as u32, T::MAX_EID - 6);
+
+        assert!(set.remove(256));
+        assert!(!set.remove(256));
+        assert_eq!(
+            GenericBitSet::<T>::from_bytes(&set.to_bytes()).map(|read| read.len()),
+            Ok(5)
+        );
+        assert_eq!(set.drain().count(), 5);
+        assert!(set.is_empty());
+    }
 }
 
 #[cfg(all(test, feature = "parallel"))]
@@ -780,10 +2247,51 @@ mod test_parallel {
     extern crate typed_test_gen;
     use self::typed_test_gen::test_with;
 
-    use super::{BitSetAnd, BitSetLike, GenericBitSet, UnsignedInteger};
+    use super::{
+        BitSetAnd, BitSetAndAll, BitSetLike, BitSetOrAll, BitSetThreshold, GenericBitSet,
+        UnsignedInteger,
+    };
     use rayon::iter::ParallelIterator;
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u8, u16)]
+    fn par_iter_small_words<T: UnsignedInteger + Send + Sync>() {
+        let set: GenericBitSet<T> = (0..T::MAX_EID).filter(|i| i % 3 == 0).collect();
+        assert_eq!((&set).par_iter().count(), (&set).iter().count());
+        let mut indices = (&set).par_iter().collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, (0..T::MAX_EID).step_by(3).collect::<Vec<_>>());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn par_iter_and_all_or_all<T: UnsignedInteger + Send + Sync>() {
+        let sets: Vec<GenericBitSet<T>> = (2..5)
+            .map(|n| (0..100_000).filter(|i| i % n == 0).collect())
+            .collect();
+        let and = BitSetAndAll::new(&sets[..]);
+        assert_eq!((&and).par_iter().count(), (&and).iter().count());
+        assert_eq!((&and).par_iter().count(), 8_334);
+        let or = BitSetOrAll::new(&sets[..]);
+        assert_eq!(or.par_iter().count(), 66_667);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn par_iter_threshold<T: UnsignedInteger + Send + Sync>() {
+        let sets: Vec<GenericBitSet<T>> = (2..5)
+            .map(|n| (0..100_000).filter(|i| i % n == 0).collect())
+            .collect();
+        let votes = BitSetThreshold::new(&sets[..], 2);
+        assert_eq!((&votes).par_iter().count(), (&votes).iter().count());
+        let mut indices = votes.par_iter().collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(
+            indices,
+            (0..100_000)
+                .filter(|i| i % 4 == 0 || i % 6 == 0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
     fn par_iter_one<T: UnsignedInteger + Send + Sync>() {
         let step = 5000;
         let tests = 1_048_576 / step;
@@ -798,7 +2306,7 @@ mod test_parallel {
         assert_eq!(set.par_iter().count(), 1);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn par_iter_random_add<T: UnsignedInteger + Send + Sync>() {
         use rand::prelude::*;
         use std::collections::HashSet;
@@ -847,7 +2355,33 @@ mod test_parallel {
         }
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
+    fn par_iter_range<T: UnsignedInteger + Send + Sync>() {
+        use rand::prelude::*;
+
+        let mut set = GenericBitSet::<T>::new();
+        let mut rng = thread_rng();
+        let limit = 1_048_576;
+        for _ in 0..(limit / 10) {
+            set.add(rng.gen_range(0, limit));
+        }
+
+        for _ in 0..20 {
+            let a = rng.gen_range(0, limit);
+            let b = rng.gen_range(a, limit);
+            let expected = (&set).iter_range(a..=b).collect::<Vec<_>>();
+            for splits in 1..4 {
+                let mut collected = (&set)
+                    .par_iter_range(a..=b)
+                    .layers_split(splits)
+                    .collect::<Vec<_>>();
+                collected.sort();
+                assert_eq!(collected, expected);
+            }
+        }
+    }
+
+    #[test_with(u32, u64, usize, u128)]
     fn par_iter_odd_even<T: UnsignedInteger + Send + Sync>() {
         let mut odd = GenericBitSet::<T>::new();
         let mut even = GenericBitSet::<T>::new();
@@ -864,7 +2398,7 @@ mod test_parallel {
         assert_eq!(BitSetAnd(&odd, &even).par_iter().count(), 0);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn par_iter_clusters<T: UnsignedInteger + Send + Sync>() {
         use std::collections::HashSet;
         use std::sync::{Arc, Mutex};
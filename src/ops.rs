@@ -1,39 +1,48 @@
 use std::iter::{FromIterator, IntoIterator};
 use std::marker::PhantomData;
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
-use std::usize;
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub, SubAssign,
+};
 
+use storage::Storage;
 use util::*;
 
-use {AtomicBitSet, BitIter, BitSetLike, DrainableBitSet, GenericBitSet};
+use {
+    BitIter, BitSetIntervals, BitSetLike, BitSetRange, DrainableBitSet, GenericAtomicBitSet,
+    GenericBitSet,
+};
 
-impl<'a, B, T> BitOrAssign<&'a B> for GenericBitSet<T>
+impl<B, T, const L: usize, S> BitOrAssign<&B> for GenericBitSet<T, L, S>
 where
     T: UnsignedInteger,
-    B: BitSetLike<Underlying = T>,
+    S: Storage<T, L>,
+    B: BitSetLike<L, Underlying = T>,
 {
     fn bitor_assign(&mut self, lhs: &B) {
         use iter::State::Continue;
         let mut iter = lhs.iter();
-        while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
             let lower = level - 1;
             let idx = iter.prefix[lower] as usize >> T::LOG_BITS;
-            *self.layer_mut(lower, idx) |= lhs.get_from_layer(lower, idx);
+            if lower == 0 {
+                let word = self.layer0(idx) | lhs.layer0(idx);
+                self.set_layer0(idx, word);
+            }
         }
-        self.layer3 |= lhs.layer3();
     }
 }
 
-impl<'a, B, T> BitAndAssign<&'a B> for GenericBitSet<T>
+impl<B, T, const L: usize, S> BitAndAssign<&B> for GenericBitSet<T, L, S>
 where
     T: UnsignedInteger,
-    B: BitSetLike<Underlying = T>,
+    S: Storage<T, L>,
+    B: BitSetLike<L, Underlying = T>,
 {
     fn bitand_assign(&mut self, lhs: &B) {
         use iter::State::*;
         let mut iter = lhs.iter();
-        iter.masks[LAYERS - 1] &= self.layer3();
-        while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
+        iter.masks[L - 1] &= self.get_from_layer(L - 1, 0);
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
             let lower = level - 1;
             let idx = iter.prefix[lower] as usize >> T::LOG_BITS;
             let our_layer = self.get_from_layer(lower, idx);
@@ -41,90 +50,75 @@ where
 
             iter.masks[lower] &= our_layer;
 
-            let mut masks = [T::ZERO; LAYERS];
+            let mut masks = [T::ZERO; L];
             masks[lower] = our_layer & !their_layer;
             BitIter::new(&mut *self, masks, iter.prefix).clear();
 
-            *self.layer_mut(lower, idx) &= their_layer;
+            if lower == 0 {
+                self.set_layer0(idx, our_layer & their_layer);
+            }
         }
-        let mut masks = [T::ZERO; LAYERS];
-        masks[LAYERS - 1] = self.layer3() & !lhs.layer3();
-        BitIter::new(&mut *self, masks, [0; LAYERS - 1]).clear();
-
-        self.layer3 &= lhs.layer3();
+        let (ours, theirs) = (self.get_from_layer(L - 1, 0), lhs.get_from_layer(L - 1, 0));
+        let mut masks = [T::ZERO; L];
+        masks[L - 1] = ours & !theirs;
+        BitIter::new(&mut *self, masks, [0; L]).clear();
     }
 }
 
-impl<'a, B, T> BitXorAssign<&'a B> for GenericBitSet<T>
+impl<B, T, const L: usize, S> BitXorAssign<&B> for GenericBitSet<T, L, S>
 where
     T: UnsignedInteger,
-    B: BitSetLike<Underlying = T>,
+    S: Storage<T, L>,
+    B: BitSetLike<L, Underlying = T>,
 {
     fn bitxor_assign(&mut self, lhs: &B) {
         use iter::State::*;
         let mut iter = lhs.iter();
-        while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
             let lower = level - 1;
             let idx = iter.prefix[lower] as usize >> T::LOG_BITS;
 
             if lower == 0 {
-                *self.layer_mut(lower, idx) ^= lhs.get_from_layer(lower, idx);
-
-                let mut change_bit = |level| {
-                    let lower = level - 1;
-                    let h = iter.prefix.get(level).cloned().unwrap_or(0);
-                    let l = iter.prefix[lower] >> T::LOG_BITS;
-                    let mask = T::ONE << T::from_u32(l & !h);
-
-                    if self.get_from_layer(lower, l as usize) == T::ZERO {
-                        *self.layer_mut(level, h as usize >> T::LOG_BITS) &= !mask;
-                    } else {
-                        *self.layer_mut(level, h as usize >> T::LOG_BITS) |= mask;
-                    }
-                };
-
-                change_bit(level);
-                if iter.masks[level] == T::ZERO {
-                    (2..LAYERS).for_each(change_bit);
-                }
+                let word = self.layer0(idx) ^ lhs.layer0(idx);
+                self.set_layer0(idx, word);
             }
         }
     }
 }
 
+impl<B, T, const L: usize, S> SubAssign<&B> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+    B: BitSetLike<L, Underlying = T>,
+{
+    fn sub_assign(&mut self, lhs: &B) {
+        self.remove_all(lhs);
+    }
+}
+
 /// `BitSetAnd` takes two [`BitSetLike`] items, and merges the masks
 /// returning a new virtual set, which represents an intersection of the
 /// two original sets.
 ///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 #[derive(Debug, Clone)]
-pub struct BitSetAnd<A, B>(pub A, pub B)
-where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>;
+pub struct BitSetAnd<A, B, const L: usize = LAYERS>(pub A, pub B);
 
-impl<A, B> BitSetLike for BitSetAnd<A, B>
+impl<A, B, const L: usize> BitSetLike<L> for BitSetAnd<A, B, L>
 where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>,
+    A: BitSetLike<L>,
+    B: BitSetLike<L, Underlying = A::Underlying>,
 {
     type Underlying = A::Underlying;
 
     #[inline]
-    fn layer3(&self) -> Self::Underlying {
-        self.0.layer3() & self.1.layer3()
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) & self.1.get_from_layer(layer, idx)
     }
     #[inline]
-    fn layer2(&self, i: usize) -> Self::Underlying {
-        self.0.layer2(i) & self.1.layer2(i)
-    }
-    #[inline]
-    fn layer1(&self, i: usize) -> Self::Underlying {
-        self.0.layer1(i) & self.1.layer1(i)
-    }
-    #[inline]
-    fn layer0(&self, i: usize) -> Self::Underlying {
-        self.0.layer0(i) & self.1.layer0(i)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) & self.1.get_full_from_layer(layer, idx)
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
@@ -132,10 +126,10 @@ where
     }
 }
 
-impl<A, B> DrainableBitSet for BitSetAnd<A, B>
+impl<A, B, const L: usize> DrainableBitSet<L> for BitSetAnd<A, B, L>
 where
-    A: DrainableBitSet,
-    B: DrainableBitSet<Underlying = A::Underlying>,
+    A: DrainableBitSet<L>,
+    B: DrainableBitSet<L, Underlying = A::Underlying>,
 {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
@@ -155,33 +149,22 @@ where
 ///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 #[derive(Debug, Clone)]
-pub struct BitSetOr<A, B>(pub A, pub B)
-where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>;
+pub struct BitSetOr<A, B, const L: usize = LAYERS>(pub A, pub B);
 
-impl<A, B> BitSetLike for BitSetOr<A, B>
+impl<A, B, const L: usize> BitSetLike<L> for BitSetOr<A, B, L>
 where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>,
+    A: BitSetLike<L>,
+    B: BitSetLike<L, Underlying = A::Underlying>,
 {
     type Underlying = A::Underlying;
 
     #[inline]
-    fn layer3(&self) -> Self::Underlying {
-        self.0.layer3() | self.1.layer3()
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) | self.1.get_from_layer(layer, idx)
     }
     #[inline]
-    fn layer2(&self, i: usize) -> Self::Underlying {
-        self.0.layer2(i) | self.1.layer2(i)
-    }
-    #[inline]
-    fn layer1(&self, i: usize) -> Self::Underlying {
-        self.0.layer1(i) | self.1.layer1(i)
-    }
-    #[inline]
-    fn layer0(&self, i: usize) -> Self::Underlying {
-        self.0.layer0(i) | self.1.layer0(i)
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) | self.1.get_full_from_layer(layer, idx)
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
@@ -189,10 +172,10 @@ where
     }
 }
 
-impl<A, B> DrainableBitSet for BitSetOr<A, B>
+impl<A, B, const L: usize> DrainableBitSet<L> for BitSetOr<A, B, L>
 where
-    A: DrainableBitSet,
-    B: DrainableBitSet<Underlying = A::Underlying>,
+    A: DrainableBitSet<L>,
+    B: DrainableBitSet<L, Underlying = A::Underlying>,
 {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
@@ -206,36 +189,94 @@ where
     }
 }
 
-/// `BitSetNot` takes a [`BitSetLike`] item, and produced an inverted virtual set.
-/// Note: the implementation is sub-optimal because layers 1-3 are not active.
+/// `BitSetAndNot` takes two [`BitSetLike`] items, and returns a new virtual
+/// set, which represents the difference of the two original sets: the
+/// indices of the first set which aren't in the second one.
+///
+/// This is the same set as `BitSetAnd(a, BitSetNot(b))`, but its summary
+/// layers come from the first set, only pruned by the full blocks of the
+/// second one, so the words of the second set are only read at layer 0.
 ///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 #[derive(Debug, Clone)]
-pub struct BitSetNot<A>(pub A)
-where
-    A: BitSetLike;
+pub struct BitSetAndNot<A, B, const L: usize = LAYERS>(pub A, pub B);
 
-impl<A> BitSetLike for BitSetNot<A>
+impl<A, B, const L: usize> BitSetLike<L> for BitSetAndNot<A, B, L>
 where
-    A: BitSetLike,
+    A: BitSetLike<L>,
+    B: BitSetLike<L, Underlying = A::Underlying>,
 {
     type Underlying = A::Underlying;
 
     #[inline]
-    fn layer3(&self) -> A::Underlying {
-        !A::Underlying::ZERO
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) & !self.1.get_full_from_layer(layer, idx)
     }
     #[inline]
-    fn layer2(&self, _: usize) -> A::Underlying {
-        !A::Underlying::ZERO
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) & !self.1.get_from_layer(layer, idx)
     }
     #[inline]
-    fn layer1(&self, _: usize) -> A::Underlying {
-        !A::Underlying::ZERO
+    fn contains(&self, i: Index) -> bool {
+        self.0.contains(i) && !self.1.contains(i)
     }
+}
+
+impl<A, B, const L: usize> DrainableBitSet<L> for BitSetAndNot<A, B, L>
+where
+    A: DrainableBitSet<L>,
+    B: BitSetLike<L, Underlying = A::Underlying>,
+{
     #[inline]
-    fn layer0(&self, i: usize) -> A::Underlying {
-        !self.0.layer0(i)
+    fn remove(&mut self, i: Index) -> bool {
+        if self.contains(i) {
+            self.0.remove(i);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// `BitSetNot` takes a [`BitSetLike`] item, and produced an inverted virtual set.
+///
+/// Its summary layers are built from the full block summary of the inner
+/// set, so the blocks which are full in the inner set are skipped. Inner sets
+/// that don't track full blocks, see [`get_full_from_layer`], still visit every
+/// layer 0 word.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`get_full_from_layer`]: ../trait.BitSetLike.html#method.get_full_from_layer
+#[derive(Debug, Clone)]
+pub struct BitSetNot<A, const L: usize = LAYERS>(pub A);
+
+impl<A, const L: usize> BitSetLike<L> for BitSetNot<A, L>
+where
+    A: BitSetLike<L>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> A::Underlying {
+        let word = if layer == 0 {
+            !self.0.get_from_layer(0, idx)
+        } else {
+            !self.0.get_full_from_layer(layer, idx)
+        };
+        if layer == L - 1 {
+            word & top_mask(L)
+        } else {
+            word
+        }
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> A::Underlying {
+        let word = !self.0.get_from_layer(layer, idx);
+        if layer == L - 1 {
+            word & top_mask(L)
+        } else {
+            word
+        }
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
@@ -249,55 +290,36 @@ where
 ///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 #[derive(Debug, Clone)]
-pub struct BitSetXor<A, B>(pub A, pub B)
-where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>;
+pub struct BitSetXor<A, B, const L: usize = LAYERS>(pub A, pub B);
 
-impl<A, B> BitSetLike for BitSetXor<A, B>
+impl<A, B, const L: usize> BitSetLike<L> for BitSetXor<A, B, L>
 where
-    A: BitSetLike,
-    B: BitSetLike<Underlying = A::Underlying>,
+    A: BitSetLike<L>,
+    B: BitSetLike<L, Underlying = A::Underlying>,
 {
     type Underlying = A::Underlying;
 
     #[inline]
-    fn layer3(&self) -> Self::Underlying {
-        let xor = BitSetAnd(
-            BitSetOr(&self.0, &self.1),
-            BitSetNot(BitSetAnd(&self.0, &self.1)),
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let xor = BitSetAnd::<_, _, L>(
+            BitSetOr::<_, _, L>(&self.0, &self.1),
+            BitSetNot::<_, L>(BitSetAnd::<_, _, L>(&self.0, &self.1)),
         );
-        xor.layer3()
+        xor.get_from_layer(layer, idx)
     }
     #[inline]
-    fn layer2(&self, id: usize) -> Self::Underlying {
-        let xor = BitSetAnd(
-            BitSetOr(&self.0, &self.1),
-            BitSetNot(BitSetAnd(&self.0, &self.1)),
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let xor = BitSetAnd::<_, _, L>(
+            BitSetOr::<_, _, L>(&self.0, &self.1),
+            BitSetNot::<_, L>(BitSetAnd::<_, _, L>(&self.0, &self.1)),
         );
-        xor.layer2(id)
-    }
-    #[inline]
-    fn layer1(&self, id: usize) -> Self::Underlying {
-        let xor = BitSetAnd(
-            BitSetOr(&self.0, &self.1),
-            BitSetNot(BitSetAnd(&self.0, &self.1)),
-        );
-        xor.layer1(id)
-    }
-    #[inline]
-    fn layer0(&self, id: usize) -> Self::Underlying {
-        let xor = BitSetAnd(
-            BitSetOr(&self.0, &self.1),
-            BitSetNot(BitSetAnd(&self.0, &self.1)),
-        );
-        xor.layer0(id)
+        xor.get_full_from_layer(layer, idx)
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
-        BitSetAnd(
-            BitSetOr(&self.0, &self.1),
-            BitSetNot(BitSetAnd(&self.0, &self.1)),
+        BitSetAnd::<_, _, L>(
+            BitSetOr::<_, _, L>(&self.0, &self.1),
+            BitSetNot::<_, L>(BitSetAnd::<_, _, L>(&self.0, &self.1)),
         )
         .contains(i)
     }
@@ -306,117 +328,469 @@ where
 /// `BitSetAll` is a bitset with all bits set. Essentially the same as
 /// `BitSetNot(BitSet::new())` but without any allocation.
 #[derive(Debug, Clone)]
-pub struct BitSetAll<T: UnsignedInteger> {
+pub struct BitSetAll<T: UnsignedInteger, const L: usize = LAYERS> {
     _phantom: PhantomData<T>,
 }
-impl<T: UnsignedInteger> BitSetLike for BitSetAll<T> {
+impl<T: UnsignedInteger, const L: usize> BitSetLike<L> for BitSetAll<T, L> {
     type Underlying = T;
 
     #[inline]
-    fn layer3(&self) -> Self::Underlying {
-        T::MAX
+    fn get_from_layer(&self, layer: usize, _idx: usize) -> Self::Underlying {
+        if layer == L - 1 {
+            top_mask(L)
+        } else {
+            T::MAX
+        }
     }
     #[inline]
-    fn layer2(&self, _id: usize) -> Self::Underlying {
-        T::MAX
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.get_from_layer(layer, idx)
     }
     #[inline]
-    fn layer1(&self, _id: usize) -> Self::Underlying {
-        T::MAX
+    fn contains(&self, _i: Index) -> bool {
+        true
     }
+}
+
+/// `BitSetAndAll` takes any number of [`BitSetLike`] items, as a slice, a
+/// `Vec` or an array, and returns a new virtual set, which represents the
+/// intersection of all of them.
+///
+/// The layers are folded word by word, starting with the set holding the
+/// fewest indices, so a word is usually cleared by the sparsest set before
+/// the others are read. The intersection of no sets is empty.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAndAll, BitSetLike};
+///
+/// let sets: Vec<BitSet> = vec![(0..100).collect(), (50..150).collect(), (90..95).collect()];
+/// let and = BitSetAndAll::new(&sets[..]);
+/// assert_eq!(and.iter().collect::<Vec<_>>(), vec![90, 91, 92, 93, 94]);
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitSetAndAll<S, C = Vec<S>, const L: usize = LAYERS>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    sets: C,
+    // The indices of `sets`, sparsest first.
+    order: Vec<usize>,
+    _phantom: PhantomData<S>,
+}
+
+impl<S, C, const L: usize> BitSetAndAll<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    /// Creates the intersection of `sets`.
+    ///
+    /// This counts the indices of every set once, to order them.
+    pub fn new(sets: C) -> Self {
+        let mut order: Vec<usize> = (0..sets.as_ref().len()).collect();
+        order.sort_by_cached_key(|&i| sets.as_ref()[i].count());
+        BitSetAndAll {
+            sets,
+            order,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the intersected sets, in their original order.
+    pub fn sets(&self) -> &[S] {
+        self.sets.as_ref()
+    }
+
+    /// Returns the sets in the order they are intersected.
+    fn ordered(&self) -> impl Iterator<Item = &S> {
+        let sets = self.sets.as_ref();
+        self.order.iter().map(move |&i| &sets[i])
+    }
+}
+
+impl<S, C, const L: usize> BitSetLike<L> for BitSetAndAll<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    type Underlying = S::Underlying;
+
     #[inline]
-    fn layer0(&self, _id: usize) -> Self::Underlying {
-        T::MAX
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        if self.order.is_empty() {
+            return Self::Underlying::ZERO;
+        }
+        let mut word = !Self::Underlying::ZERO;
+        for set in self.ordered() {
+            word &= set.get_from_layer(layer, idx);
+            if word == Self::Underlying::ZERO {
+                break;
+            }
+        }
+        word
     }
     #[inline]
-    fn contains(&self, _i: Index) -> bool {
-        true
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        if self.order.is_empty() {
+            return Self::Underlying::ZERO;
+        }
+        let mut word = !Self::Underlying::ZERO;
+        for set in self.ordered() {
+            word &= set.get_full_from_layer(layer, idx);
+            if word == Self::Underlying::ZERO {
+                break;
+            }
+        }
+        word
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        !self.order.is_empty() && self.ordered().all(|set| set.contains(i))
+    }
+}
+
+/// `BitSetOrAll` takes any number of [`BitSetLike`] items, as a slice, a
+/// `Vec` or an array, and returns a new virtual set, which represents the
+/// union of all of them.
+///
+/// The layers are folded word by word, stopping once a word is full.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetLike, BitSetOrAll};
+///
+/// let sets: [BitSet; 3] = [(0..2).collect(), (5..7).collect(), (1..3).collect()];
+/// let or = BitSetOrAll::new(sets);
+/// assert_eq!(or.iter().collect::<Vec<_>>(), vec![0, 1, 2, 5, 6]);
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitSetOrAll<S, C = Vec<S>, const L: usize = LAYERS>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    sets: C,
+    _phantom: PhantomData<S>,
+}
+
+impl<S, C, const L: usize> BitSetOrAll<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    /// Creates the union of `sets`.
+    pub fn new(sets: C) -> Self {
+        BitSetOrAll {
+            sets,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the merged sets.
+    pub fn sets(&self) -> &[S] {
+        self.sets.as_ref()
+    }
+}
+
+impl<S, C, const L: usize> BitSetLike<L> for BitSetOrAll<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    type Underlying = S::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let mut word = Self::Underlying::ZERO;
+        for set in self.sets() {
+            word |= set.get_from_layer(layer, idx);
+            if word == Self::Underlying::MAX {
+                break;
+            }
+        }
+        word
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let mut word = Self::Underlying::ZERO;
+        for set in self.sets() {
+            word |= set.get_full_from_layer(layer, idx);
+            if word == Self::Underlying::MAX {
+                break;
+            }
+        }
+        word
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.sets().iter().any(|set| set.contains(i))
+    }
+}
+
+/// `BitSetThreshold` takes any number of [`BitSetLike`] items, as a slice,
+/// a `Vec` or an array, and returns a new virtual set, which represents the
+/// indices present in at least `k` of them.
+///
+/// A threshold of 1 is the union of the sets, and a threshold of the number
+/// of sets is their intersection. Layer 0 counts the sets holding each index
+/// bitwise, a word at a time.
+///
+/// The summary layers count the summary words of the inputs the same way,
+/// instead of taking their OR. They still cover every index of the set: an
+/// index in at least `k` sets has the summary bits above it set in each of
+/// those sets, so its blocks are reached by at least `k` summary words too.
+/// Blocks which fewer than `k` sets reach are pruned without visiting them.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetLike, BitSetThreshold};
+///
+/// let sets: Vec<BitSet> = vec![(0..4).collect(), (2..6).collect(), (3..8).collect()];
+/// let votes = BitSetThreshold::new(&sets[..], 2);
+/// assert_eq!(votes.iter().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitSetThreshold<S, C = Vec<S>, const L: usize = LAYERS>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    sets: C,
+    k: usize,
+    _phantom: PhantomData<S>,
+}
+
+impl<S, C, const L: usize> BitSetThreshold<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    /// Creates the set of the indices present in at least `k` of `sets`.
+    ///
+    /// With a `k` of 0, every index is in the set.
+    pub fn new(sets: C, k: usize) -> Self {
+        BitSetThreshold {
+            sets,
+            k,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the counted sets.
+    pub fn sets(&self) -> &[S] {
+        self.sets.as_ref()
+    }
+
+    /// Returns the number of sets an index must be in.
+    pub fn threshold(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the bits set in at least `k` of `words`.
+    ///
+    /// The count of every bit is kept in bit planes, `planes[i]` holding
+    /// bit `i` of the counts, and compared to `k` from the highest bit.
+    fn count_words<I>(&self, layer: usize, words: I) -> S::Underlying
+    where
+        I: Iterator<Item = S::Underlying>,
+    {
+        let zero = S::Underlying::ZERO;
+        if self.k == 0 {
+            return if layer == L - 1 { top_mask(L) } else { !zero };
+        }
+        if self.k > self.sets().len() {
+            return zero;
+        }
+        let bits = (usize::BITS - self.sets().len().leading_zeros()) as usize;
+        let mut planes = [zero; usize::BITS as usize];
+        for word in words {
+            let mut carry = word;
+            for plane in &mut planes[..bits] {
+                if carry == zero {
+                    break;
+                }
+                let sum = *plane ^ carry;
+                carry = *plane & carry;
+                *plane = sum;
+            }
+        }
+        let (mut greater, mut equal) = (zero, !zero);
+        for (bit, &plane) in planes[..bits].iter().enumerate().rev() {
+            if self.k >> bit & 1 == 1 {
+                equal &= plane;
+            } else {
+                greater |= equal & plane;
+                equal &= !plane;
+            }
+        }
+        greater | equal
+    }
+}
+
+impl<S, C, const L: usize> BitSetLike<L> for BitSetThreshold<S, C, L>
+where
+    S: BitSetLike<L>,
+    C: AsRef<[S]>,
+{
+    type Underlying = S::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let words = self.sets().iter().map(|set| set.get_from_layer(layer, idx));
+        self.count_words(layer, words)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let words = self
+            .sets()
+            .iter()
+            .map(|set| set.get_full_from_layer(layer, idx));
+        self.count_words(layer, words)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.k == 0
+            || self
+                .sets()
+                .iter()
+                .filter(|set| set.contains(i))
+                .nth(self.k - 1)
+                .is_some()
     }
 }
 
 macro_rules! operator {
     ( impl < ( $( $lifetime:tt )* ) ( $( $arg:ident ),* ) > for $bitset:ty ) => {
-        impl<$( $lifetime, )* T, $( $arg ),*> IntoIterator for $bitset
+        operator!(impl<($( $lifetime )*)($( $arg ),*)()> for $bitset where ());
+    };
+    ( impl < ( $( $lifetime:tt )* ) ( $( $arg:ident ),* ) ( $( $param:ident ),* ) > for $bitset:ty
+        where ( $( $bounds:tt )* ) ) => {
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* const L: usize> IntoIterator for $bitset
             where
                 T: UnsignedInteger,
-                $( $arg: BitSetLike<Underlying = T> ),*
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
         {
-            type Item = <BitIter<Self> as Iterator>::Item;
-            type IntoIter = BitIter<Self>;
+            type Item = <BitIter<Self, L> as Iterator>::Item;
+            type IntoIter = BitIter<Self, L>;
             fn into_iter(self) -> Self::IntoIter {
                 self.iter()
             }
         }
 
-        impl<$( $lifetime, )* T, $( $arg ),*> Not for $bitset
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* const L: usize> Not for $bitset
             where
                 T: UnsignedInteger,
-                $( $arg: BitSetLike<Underlying = T> ),*
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
         {
-            type Output = BitSetNot<Self>;
+            type Output = BitSetNot<Self, L>;
             fn not(self) -> Self::Output {
                 BitSetNot(self)
             }
         }
 
-        impl<$( $lifetime, )* T, $( $arg, )* OtherBitSetLike> BitAnd<OtherBitSetLike> for $bitset
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* OtherBitSetLike, const L: usize> BitAnd<OtherBitSetLike> for $bitset
             where
                 T: UnsignedInteger,
-                OtherBitSetLike: BitSetLike<Underlying = T>,
-                $( $arg: BitSetLike<Underlying = T> ),*
+                OtherBitSetLike: BitSetLike<L, Underlying = T>,
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
         {
-            type Output = BitSetAnd<Self, OtherBitSetLike>;
+            type Output = BitSetAnd<Self, OtherBitSetLike, L>;
             fn bitand(self, rhs: OtherBitSetLike) -> Self::Output {
                 BitSetAnd(self, rhs)
             }
         }
 
-        impl<$( $lifetime, )* T, $( $arg, )* OtherBitSetLike> BitOr<OtherBitSetLike> for $bitset
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* OtherBitSetLike, const L: usize> BitOr<OtherBitSetLike> for $bitset
             where
                 T: UnsignedInteger,
-                OtherBitSetLike: BitSetLike<Underlying = T>,
-                $( $arg: BitSetLike<Underlying = T> ),*
+                OtherBitSetLike: BitSetLike<L, Underlying = T>,
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
         {
-            type Output = BitSetOr<Self, OtherBitSetLike>;
+            type Output = BitSetOr<Self, OtherBitSetLike, L>;
             fn bitor(self, rhs: OtherBitSetLike) -> Self::Output {
                 BitSetOr(self, rhs)
             }
         }
 
-        impl<$( $lifetime, )* T, $( $arg, )* OtherBitSetLike> BitXor<OtherBitSetLike> for $bitset
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* OtherBitSetLike, const L: usize> BitXor<OtherBitSetLike> for $bitset
             where
                 T: UnsignedInteger,
-                OtherBitSetLike: BitSetLike<Underlying = T>,
-                $( $arg: BitSetLike<Underlying = T> ),*
+                OtherBitSetLike: BitSetLike<L, Underlying = T>,
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
         {
-            type Output = BitSetXor<Self, OtherBitSetLike>;
+            type Output = BitSetXor<Self, OtherBitSetLike, L>;
             fn bitxor(self, rhs: OtherBitSetLike) -> Self::Output {
                 BitSetXor(self, rhs)
             }
         }
 
+        impl<$( $lifetime, )* T, $( $arg, )* $( $param, )* OtherBitSetLike, const L: usize> Sub<OtherBitSetLike> for $bitset
+            where
+                T: UnsignedInteger,
+                OtherBitSetLike: BitSetLike<L, Underlying = T>,
+                $( $arg: BitSetLike<L, Underlying = T>, )*
+                $( $bounds )*
+        {
+            type Output = BitSetAndNot<Self, OtherBitSetLike, L>;
+            fn sub(self, rhs: OtherBitSetLike) -> Self::Output {
+                BitSetAndNot(self, rhs)
+            }
+        }
+
     }
 }
 
-operator!(impl<()()> for GenericBitSet<T>);
-operator!(impl<('a)()> for &'a GenericBitSet<T>);
-operator!(impl<()(A)> for BitSetNot<A>);
-operator!(impl<('a)(A)> for &'a BitSetNot<A>);
-operator!(impl<()(A, B)> for BitSetAnd<A, B>);
-operator!(impl<('a)(A, B)> for &'a BitSetAnd<A, B>);
-operator!(impl<()(A, B)> for BitSetOr<A, B>);
-operator!(impl<('a)(A, B)> for &'a BitSetOr<A, B>);
-operator!(impl<()(A, B)> for BitSetXor<A, B>);
-operator!(impl<('a)(A, B)> for &'a BitSetXor<A, B>);
-operator!(impl<()()> for BitSetAll<T>);
-operator!(impl<('a)()> for &'a BitSetAll<T>);
-
-impl<T: UnsignedInteger> FromIterator<Index> for GenericBitSet<T> {
+operator!(impl<()()(S)> for GenericBitSet<T, L, S> where (S: Storage<T, L>));
+operator!(impl<('a)()(S)> for &'a GenericBitSet<T, L, S> where (S: Storage<T, L>));
+operator!(impl<()(A)> for BitSetNot<A, L>);
+operator!(impl<('a)(A)> for &'a BitSetNot<A, L>);
+operator!(impl<()(A, B)> for BitSetAnd<A, B, L>);
+operator!(impl<('a)(A, B)> for &'a BitSetAnd<A, B, L>);
+operator!(impl<()(A, B)> for BitSetOr<A, B, L>);
+operator!(impl<('a)(A, B)> for &'a BitSetOr<A, B, L>);
+operator!(impl<()(A, B)> for BitSetXor<A, B, L>);
+operator!(impl<('a)(A, B)> for &'a BitSetXor<A, B, L>);
+operator!(impl<()(A, B)> for BitSetAndNot<A, B, L>);
+operator!(impl<('a)(A, B)> for &'a BitSetAndNot<A, B, L>);
+operator!(impl<()(S)(C)> for BitSetAndAll<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<('a)(S)(C)> for &'a BitSetAndAll<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<()(S)(C)> for BitSetOrAll<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<('a)(S)(C)> for &'a BitSetOrAll<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<()(S)(C)> for BitSetThreshold<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<('a)(S)(C)> for &'a BitSetThreshold<S, C, L> where (C: AsRef<[S]>));
+operator!(impl<()()> for BitSetAll<T, L>);
+operator!(impl<('a)()> for &'a BitSetAll<T, L>);
+operator!(impl<()()> for BitSetRange<T, L>);
+operator!(impl<('a)()> for &'a BitSetRange<T, L>);
+operator!(impl<()()> for BitSetIntervals<T, L>);
+operator!(impl<('a)()> for &'a BitSetIntervals<T, L>);
+
+impl<T, const L: usize, S> FromIterator<Index> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     fn from_iter<I>(iter: I) -> Self
     where
         I: IntoIterator<Item = Index>,
     {
-        let mut bitset = Self::new();
+        let mut bitset = Self::default();
         for item in iter {
             bitset.add(item);
         }
@@ -424,12 +798,16 @@ impl<T: UnsignedInteger> FromIterator<Index> for GenericBitSet<T> {
     }
 }
 
-impl<'a, T: UnsignedInteger> FromIterator<&'a Index> for GenericBitSet<T> {
+impl<'a, T, const L: usize, S> FromIterator<&'a Index> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     fn from_iter<I>(iter: I) -> Self
     where
         I: IntoIterator<Item = &'a Index>,
     {
-        let mut bitset = Self::new();
+        let mut bitset = Self::default();
         for item in iter {
             bitset.add(*item);
         }
@@ -437,7 +815,11 @@ impl<'a, T: UnsignedInteger> FromIterator<&'a Index> for GenericBitSet<T> {
     }
 }
 
-impl<T: UnsignedInteger> Extend<Index> for GenericBitSet<T> {
+impl<T, const L: usize, S> Extend<Index> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     fn extend<I>(&mut self, iter: I)
     where
         I: IntoIterator<Item = Index>,
@@ -448,7 +830,11 @@ impl<T: UnsignedInteger> Extend<Index> for GenericBitSet<T> {
     }
 }
 
-impl<'a, T: UnsignedInteger> Extend<&'a Index> for GenericBitSet<T> {
+impl<'a, T, const L: usize, S> Extend<&'a Index> for GenericBitSet<T, L, S>
+where
+    T: UnsignedInteger,
+    S: Storage<T, L>,
+{
     fn extend<I>(&mut self, iter: I)
     where
         I: IntoIterator<Item = &'a Index>,
@@ -459,141 +845,162 @@ impl<'a, T: UnsignedInteger> Extend<&'a Index> for GenericBitSet<T> {
     }
 }
 
-// All specialized implementations for `AtomicBitSet`
+// All specialized implementations for `GenericAtomicBitSet`
 
-impl FromIterator<Index> for AtomicBitSet {
-    fn from_iter<T>(iter: T) -> Self
+impl<T: AtomicUnsignedInteger> FromIterator<Index> for GenericAtomicBitSet<T> {
+    fn from_iter<I>(iter: I) -> Self
     where
-        T: IntoIterator<Item = Index>,
+        I: IntoIterator<Item = Index>,
     {
-        let mut bitset = AtomicBitSet::new();
+        let mut bitset = GenericAtomicBitSet::new();
         for item in iter {
             bitset.add(item);
         }
         bitset
     }
 }
-impl<'a> FromIterator<&'a Index> for AtomicBitSet {
-    fn from_iter<T>(iter: T) -> Self
+impl<'a, T: AtomicUnsignedInteger> FromIterator<&'a Index> for GenericAtomicBitSet<T> {
+    fn from_iter<I>(iter: I) -> Self
     where
-        T: IntoIterator<Item = &'a Index>,
+        I: IntoIterator<Item = &'a Index>,
     {
-        let mut bitset = AtomicBitSet::new();
+        let mut bitset = GenericAtomicBitSet::new();
         for item in iter {
             bitset.add(*item);
         }
         bitset
     }
 }
-impl Extend<Index> for AtomicBitSet {
-    fn extend<T>(&mut self, iter: T)
+impl<T: AtomicUnsignedInteger> Extend<Index> for GenericAtomicBitSet<T> {
+    fn extend<I>(&mut self, iter: I)
     where
-        T: IntoIterator<Item = Index>,
+        I: IntoIterator<Item = Index>,
     {
         for item in iter {
             self.add(item);
         }
     }
 }
-impl<'a> Extend<&'a Index> for AtomicBitSet {
-    fn extend<T>(&mut self, iter: T)
+impl<'a, T: AtomicUnsignedInteger> Extend<&'a Index> for GenericAtomicBitSet<T> {
+    fn extend<I>(&mut self, iter: I)
     where
-        T: IntoIterator<Item = &'a Index>,
+        I: IntoIterator<Item = &'a Index>,
     {
         for item in iter {
             self.add(*item);
         }
     }
 }
-impl IntoIterator for AtomicBitSet {
+impl<T: AtomicUnsignedInteger> IntoIterator for GenericAtomicBitSet<T> {
     type Item = <BitIter<Self> as Iterator>::Item;
     type IntoIter = BitIter<Self>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
-impl<'a> IntoIterator for &'a AtomicBitSet {
+impl<T: AtomicUnsignedInteger> IntoIterator for &GenericAtomicBitSet<T> {
     type Item = <BitIter<Self> as Iterator>::Item;
     type IntoIter = BitIter<Self>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
-impl Not for AtomicBitSet {
+impl<T: AtomicUnsignedInteger> Not for GenericAtomicBitSet<T> {
     type Output = BitSetNot<Self>;
     fn not(self) -> Self::Output {
         BitSetNot(self)
     }
 }
-impl<'a> Not for &'a AtomicBitSet {
+impl<T: AtomicUnsignedInteger> Not for &GenericAtomicBitSet<T> {
     type Output = BitSetNot<Self>;
     fn not(self) -> Self::Output {
         BitSetNot(self)
     }
 }
-impl<OtherBitSetLike> BitAnd<OtherBitSetLike> for AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitAnd<OtherBitSetLike> for GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetAnd<Self, OtherBitSetLike>;
     fn bitand(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetAnd(self, rhs)
     }
 }
-impl<'a, OtherBitSetLike> BitAnd<OtherBitSetLike> for &'a AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitAnd<OtherBitSetLike> for &GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetAnd<Self, OtherBitSetLike>;
     fn bitand(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetAnd(self, rhs)
     }
 }
-impl<OtherBitSetLike> BitOr<OtherBitSetLike> for AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitOr<OtherBitSetLike> for GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetOr<Self, OtherBitSetLike>;
     fn bitor(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetOr(self, rhs)
     }
 }
-impl<'a, OtherBitSetLike> BitOr<OtherBitSetLike> for &'a AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitOr<OtherBitSetLike> for &GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetOr<Self, OtherBitSetLike>;
     fn bitor(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetOr(self, rhs)
     }
 }
-impl<OtherBitSetLike> BitXor<OtherBitSetLike> for AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitXor<OtherBitSetLike> for GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetXor<Self, OtherBitSetLike>;
     fn bitxor(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetXor(self, rhs)
     }
 }
-impl<'a, OtherBitSetLike> BitXor<OtherBitSetLike> for &'a AtomicBitSet
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> BitXor<OtherBitSetLike> for &GenericAtomicBitSet<T>
 where
-    OtherBitSetLike: BitSetLike<Underlying = usize>,
+    OtherBitSetLike: BitSetLike<Underlying = T>,
 {
     type Output = BitSetXor<Self, OtherBitSetLike>;
     fn bitxor(self, rhs: OtherBitSetLike) -> Self::Output {
         BitSetXor(self, rhs)
     }
 }
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> Sub<OtherBitSetLike> for GenericAtomicBitSet<T>
+where
+    OtherBitSetLike: BitSetLike<Underlying = T>,
+{
+    type Output = BitSetAndNot<Self, OtherBitSetLike>;
+    fn sub(self, rhs: OtherBitSetLike) -> Self::Output {
+        BitSetAndNot(self, rhs)
+    }
+}
+impl<T: AtomicUnsignedInteger, OtherBitSetLike> Sub<OtherBitSetLike> for &GenericAtomicBitSet<T>
+where
+    OtherBitSetLike: BitSetLike<Underlying = T>,
+{
+    type Output = BitSetAndNot<Self, OtherBitSetLike>;
+    fn sub(self, rhs: OtherBitSetLike) -> Self::Output {
+        BitSetAndNot(self, rhs)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     extern crate typed_test_gen;
     use self::typed_test_gen::test_with;
 
-    use {BitSetLike, BitSetXor, GenericBitSet, Index, UnsignedInteger};
+    use {
+        BitSetAnd, BitSetAndAll, BitSetAndNot, BitSetLike, BitSetNot, BitSetOrAll, BitSetThreshold,
+        BitSetXor, GenericBitSet, Index, UnsignedInteger,
+    };
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn or_assign<T: UnsignedInteger>() {
         use std::collections::HashSet;
         use std::mem::size_of;
@@ -613,7 +1020,7 @@ mod tests {
         assert_eq!(c1.iter().collect::<HashSet<_>>(), &h1 | &h2);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn or_assign_random<T: UnsignedInteger>() {
         use rand::prelude::*;
 
@@ -652,7 +1059,7 @@ mod tests {
         assert_eq!(hs, set1.iter().collect());
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn and_assign<T: UnsignedInteger>() {
         use std::collections::HashSet;
         use std::mem::size_of;
@@ -672,7 +1079,7 @@ mod tests {
         assert_eq!(c1.iter().collect::<HashSet<_>>(), &h1 & &h2);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn and_assign_specific<T: UnsignedInteger>() {
         let mut c1 = GenericBitSet::<T>::new();
         c1.add(0);
@@ -689,7 +1096,17 @@ mod tests {
         assert_eq!(c1.iter().collect::<Vec<_>>(), [common]);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
+    fn and_assign_top_layer<T: UnsignedInteger>() {
+        let mut c1: GenericBitSet<T> = [5, T::MAX_EID - 1].iter().collect();
+        let c2: GenericBitSet<T> = [5].iter().collect();
+
+        c1 &= &c2;
+
+        assert_eq!(c1.iter().collect::<Vec<_>>(), [5]);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
     fn and_assign_with_modification<T: UnsignedInteger>() {
         let mut c1 = GenericBitSet::<T>::new();
         c1.add(0);
@@ -706,7 +1123,7 @@ mod tests {
         assert_eq!(c1.iter().collect::<Vec<_>>(), [0, added]);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn and_assign_random<T: UnsignedInteger>() {
         use rand::prelude::*;
 
@@ -745,7 +1162,7 @@ mod tests {
         assert_eq!(hs, set1.iter().collect());
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn xor_assign<T: UnsignedInteger>() {
         use std::collections::HashSet;
         use std::mem::size_of;
@@ -764,7 +1181,7 @@ mod tests {
         assert_eq!(c1.iter().collect::<HashSet<_>>(), &h1 ^ &h2);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn xor_assign_specific<T: UnsignedInteger>() {
         let mut c1 = GenericBitSet::<T>::new();
         c1.add(0);
@@ -783,7 +1200,7 @@ mod tests {
         assert_eq!(c1.iter().collect::<Vec<_>>(), [0, a, b]);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn xor_assign_random<T: UnsignedInteger>() {
         use rand::prelude::*;
         use std::collections::HashSet;
@@ -821,7 +1238,85 @@ mod tests {
         assert_eq!(hs, set1.iter().collect());
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
+    fn assign_len<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let limit = 1_048_576;
+        let mut rng = thread_rng();
+
+        let set1: GenericBitSet<T> = (0..limit / 100).map(|_| rng.gen_range(0, limit)).collect();
+        let set2: GenericBitSet<T> = (0..limit / 100).map(|_| rng.gen_range(0, limit)).collect();
+
+        let mut or = set1.clone();
+        or |= &set2;
+        assert_eq!(or.len(), or.iter().count());
+
+        let mut and = set1.clone();
+        and &= &set2;
+        assert_eq!(and.len(), and.iter().count());
+
+        let mut xor = set1.clone();
+        xor ^= &set2;
+        assert_eq!(xor.len(), xor.iter().count());
+
+        let mut sub = set1.clone();
+        sub -= &set2;
+        assert_eq!(sub.len(), sub.iter().count());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn sub_assign_random<T: UnsignedInteger>() {
+        use rand::prelude::*;
+        use std::collections::HashSet;
+        let limit = 1_048_576;
+        let mut rng = thread_rng();
+
+        let mut set1: GenericBitSet<T> = (0..limit / 10).map(|_| rng.gen_range(0, limit)).collect();
+        let mut set2: GenericBitSet<T> = (0..limit / 10).map(|_| rng.gen_range(0, limit)).collect();
+        set2.insert_range(1_000..100_000);
+
+        let hs1 = (&set1).iter().collect::<HashSet<_>>();
+        let hs2 = (&set2).iter().collect::<HashSet<_>>();
+        let difference = &hs1 - &hs2;
+        assert_eq!(
+            BitSetAndNot(&set1, &set2).iter().collect::<HashSet<_>>(),
+            difference
+        );
+        assert_eq!((&set1 - &set2).iter().count(), difference.len());
+
+        set1 -= &set2;
+        assert_eq!((&set1).iter().collect::<HashSet<_>>(), difference);
+        assert_eq!(set1.len(), difference.len());
+        assert_eq!(set1.next_set(1_000), set1.next_set(100_000));
+
+        set2.remove_all(&set2.clone());
+        assert!(set2.is_empty());
+        assert_eq!(set2.layer3(), T::ZERO);
+        assert_eq!(set2.first(), None);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn and_not_prunes_full_blocks<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut a = GenericBitSet::<T>::new();
+        a.insert_range(0..2 * block);
+        let mut b = GenericBitSet::<T>::new();
+        b.insert_range(block..3 * block);
+        b.remove(block + 9);
+
+        let difference = BitSetAndNot(&a, &b);
+        assert_eq!(difference.get_from_layer(1, 2), T::ZERO);
+        assert_eq!(difference.get_full_from_layer(1, 0), T::MAX);
+        assert_eq!(
+            (&difference).iter().collect::<Vec<_>>(),
+            BitSetAnd(&a, BitSetNot(&b)).iter().collect::<Vec<_>>()
+        );
+        assert_eq!((&difference).iter().count() as u32, block + 1);
+        assert_eq!(difference.last(), Some(block + 9));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
     fn operators<T: UnsignedInteger>() {
         let mut bitset = GenericBitSet::<T>::new();
         bitset.add(1);
@@ -868,9 +1363,127 @@ mod tests {
             let exclusive_sanity = bitset.clone() ^ other.clone();
             assert_eq!(collected, exclusive_sanity.iter().collect::<Vec<Index>>());
         }
+
+        {
+            let difference = &bitset - &other;
+            let collected = difference.iter().collect::<Vec<Index>>();
+            assert_eq!(collected, vec![5, 15, 200]);
+
+            let difference_sanity = bitset.clone() - other.clone();
+            assert_eq!(collected, difference_sanity.iter().collect::<Vec<Index>>());
+        }
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn and_all<T: UnsignedInteger>() {
+        let sets: Vec<GenericBitSet<T>> = vec![
+            (0..10_000).filter(|i| i % 2 == 0).collect(),
+            (0..10_000).filter(|i| i % 3 == 0).collect(),
+            (5_000..6_000).collect(),
+        ];
+        let expected: Vec<Index> = (5_000..6_000).filter(|i| i % 6 == 0).collect();
+
+        let and = BitSetAndAll::new(&sets[..]);
+        assert_eq!(and.order, vec![2, 1, 0]);
+        assert_eq!((&and).iter().collect::<Vec<_>>(), expected);
+        assert!(and.contains(5_004));
+        assert!(!and.contains(4_998));
+
+        let nested = BitSetAnd(BitSetAnd(&sets[0], &sets[1]), &sets[2]);
+        assert!(and.set_eq(&nested));
+
+        let refs: Vec<&GenericBitSet<T>> = sets.iter().collect();
+        assert_eq!((BitSetAndAll::new(refs) - &sets[0]).iter().count(), 0);
+        let array = BitSetAndAll::new([&sets[0], &sets[1]]);
+        assert_eq!(array.iter().count(), 1_667);
+
+        let none: BitSetAndAll<GenericBitSet<T>> = BitSetAndAll::new(vec![]);
+        assert!(none.is_empty());
+        assert!(!none.contains(0));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn or_all<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut sets: Vec<GenericBitSet<T>> = (0..4).map(|_| GenericBitSet::new()).collect();
+        for (i, set) in sets.iter_mut().enumerate() {
+            set.insert_range(i as u32 * block..(i as u32 + 1) * block);
+        }
+        sets[3].add(100_000);
+
+        let or = BitSetOrAll::new(&sets[..]);
+        assert_eq!((&or).iter().count() as u32, 4 * block + 1);
+        assert_eq!(or.last(), Some(100_000));
+        assert!(or.contains(3 * block));
+        assert!(!or.contains(4 * block));
+        assert_eq!(
+            or.get_full_from_layer(2, 0) & T::from_u32(0b1111),
+            T::from_u32(0b1111)
+        );
+        assert_eq!((!&or).first(), Some(4 * block));
+
+        let and_or = BitSetAndAll::new([&or, &or]) & &sets[1];
+        assert!(and_or.set_eq(&sets[1]));
+        assert!(BitSetOrAll::<GenericBitSet<T>>::new(vec![]).is_empty());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn threshold<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let limit = 100_000;
+        let mut rng = thread_rng();
+        let sets: Vec<GenericBitSet<T>> = (0..5)
+            .map(|_| (0..limit / 5).map(|_| rng.gen_range(0, limit)).collect())
+            .collect();
+        let counts: Vec<usize> = (0..limit)
+            .map(|i| sets.iter().filter(|set| set.contains(i)).count())
+            .collect();
+
+        for k in 1..7 {
+            let expected: Vec<Index> = (0..limit).filter(|&i| counts[i as usize] >= k).collect();
+            let votes = BitSetThreshold::new(&sets[..], k);
+            assert_eq!((&votes).iter().collect::<Vec<_>>(), expected);
+            assert!(expected.iter().all(|&i| votes.contains(i)));
+            assert_eq!(votes.is_empty(), expected.is_empty());
+        }
+
+        assert!(BitSetThreshold::new(&sets[..], 1).set_eq(&BitSetOrAll::new(&sets[..])));
+        assert!(BitSetThreshold::new(&sets[..], 5).set_eq(&BitSetAndAll::new(&sets[..])));
+        let all = BitSetThreshold::new(&sets[..], 0);
+        assert!(all.contains(limit * 2));
+        assert_eq!(all.last(), Some(T::MAX_EID - 1));
+        assert!(BitSetThreshold::<GenericBitSet<T>>::new(vec![], 1).is_empty());
+
+        // The summaries only reach the blocks at least `k` sets reach.
+        let far = T::MAX_EID - 1;
+        let sets: Vec<GenericBitSet<T>> = vec![[1, 2].iter().collect(), [2, far].iter().collect()];
+        let votes = BitSetThreshold::new(&sets[..], 2);
+        let top = T::ONE << T::from_u32(far >> (T::LOG_BITS * 3) as u32);
+        assert_eq!(votes.layer3() & top, T::ZERO);
+        assert_ne!(BitSetOrAll::new(&sets[..]).layer3() & top, T::ZERO);
+        assert_eq!((&votes).iter().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn threshold_full_blocks<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut sets: Vec<GenericBitSet<T>> = (0..3).map(|_| GenericBitSet::new()).collect();
+        sets[0].insert_range(0..2 * block);
+        sets[1].insert_range(block..3 * block);
+        sets[2].insert_range(0..block + 3);
+
+        let votes = BitSetThreshold::new([&sets[0], &sets[1], &sets[2]], 2);
+        assert_eq!(
+            votes.get_full_from_layer(2, 0) & T::from_u32(0b111),
+            T::from_u32(0b11)
+        );
+        assert_eq!((!&votes).first(), Some(2 * block));
+        assert_eq!((&votes - &sets[2]).iter().count() as u32, block - 3);
+        assert_eq!(votes.iter().count() as u32, 2 * block);
     }
 
-    #[test_with(u32, u64, usize)]
+    #[test_with(u32, u64, usize, u128)]
     fn xor<T: UnsignedInteger>() {
         // 0011
         let mut bitset = GenericBitSet::<T>::new();
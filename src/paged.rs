@@ -0,0 +1,389 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+use storage::Storage;
+use util::*;
+use {CapacityError, GenericBitSet};
+
+/// A `GenericPagedBitSet` is a [`GenericBitSet`] whose layers are split
+/// in pages, allocated on demand.
+///
+/// Each layer is split in pages of `T::BITS` words, the words a single
+/// word of the layer above summarizes, kept in a map by their offset. A
+/// page is only allocated once an index is added below it, and is freed
+/// again once it is empty, so very sparse sets with high indices stay
+/// small in memory: the memory grows with the number of pages in use, not
+/// with the largest index of the set.
+///
+/// It has the same operations as a `GenericBitSet`, except for the ones
+/// exposing or sizing the vectors of the default storage, and `new`: like
+/// for a `HashMap` with another hasher, an empty set is created with
+/// `default`.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSetLike, PagedBitSet};
+///
+/// let mut set = PagedBitSet::default();
+/// set.add(16_000_000);
+/// set.add(3);
+/// assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 16_000_000]);
+/// ```
+///
+/// [`GenericBitSet`]: struct.GenericBitSet.html
+pub type GenericPagedBitSet<T, const L: usize = LAYERS> = GenericBitSet<T, L, PagedStorage<T, L>>;
+
+/// A `PagedBitSet` is a `GenericPagedBitSet` of `usize` words.
+pub type PagedBitSet = GenericPagedBitSet<usize>;
+
+/// The storage of a [`GenericPagedBitSet`], where every layer is split in
+/// pages allocated on demand.
+///
+/// [`GenericPagedBitSet`]: type.GenericPagedBitSet.html
+#[derive(Clone, Debug)]
+pub struct PagedStorage<T: UnsignedInteger, const L: usize> {
+    /// The pages of every layer, by offset. A page of layer 0 holds
+    /// `T::BITS` words, and a page of the layers above is followed by the
+    /// `T::BITS` words of its full block summary.
+    pages: [BTreeMap<usize, Box<[T]>>; L],
+}
+
+impl<T: UnsignedInteger, const L: usize> Default for PagedStorage<T, L> {
+    fn default() -> Self {
+        PagedStorage {
+            pages: std::array::from_fn(|_| BTreeMap::new()),
+        }
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> PagedStorage<T, L> {
+    /// Returns the number of words in a page of layer `level`.
+    fn page_len(level: usize) -> usize {
+        match level {
+            0 => T::BITS,
+            _ => 2 * T::BITS,
+        }
+    }
+
+    /// Returns the offset of the page holding the word `idx`, and the
+    /// offset of the word within it.
+    #[inline]
+    fn split(idx: usize) -> (usize, usize) {
+        (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1))
+    }
+
+    fn page_mut(&mut self, level: usize, page: usize) -> &mut [T] {
+        self.pages[level]
+            .entry(page)
+            .or_insert_with(|| vec![T::ZERO; Self::page_len(level)].into_boxed_slice())
+    }
+
+    /// Returns the number of allocated layer 0 pages.
+    pub(crate) fn page_count(&self) -> usize {
+        self.pages[0].len()
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> Storage<T, L> for PagedStorage<T, L> {
+    #[inline]
+    fn word(&self, level: usize, idx: usize) -> T {
+        let (page, offset) = Self::split(idx);
+        self.pages[level]
+            .get(&page)
+            .map_or(T::ZERO, |page| page[offset])
+    }
+
+    #[inline]
+    fn full(&self, level: usize, idx: usize) -> T {
+        let (page, offset) = Self::split(idx);
+        self.pages[level]
+            .get(&page)
+            .map_or(T::ZERO, |page| page[T::BITS + offset])
+    }
+
+    #[inline]
+    fn word_mut(&mut self, level: usize, idx: usize) -> &mut T {
+        let (page, offset) = Self::split(idx);
+        &mut self.page_mut(level, page)[offset]
+    }
+
+    #[inline]
+    fn full_mut(&mut self, level: usize, idx: usize) -> &mut T {
+        let (page, offset) = Self::split(idx);
+        &mut self.page_mut(level, page)[T::BITS + offset]
+    }
+
+    /// Allocates the missing pages on the path of `p0`. Only the pages
+    /// themselves are allocated fallibly, not the entries of their map.
+    fn try_reserve(&mut self, p0: usize) -> Result<(), CapacityError> {
+        let mut page = p0 >> T::LOG_BITS;
+        for level in 0..L {
+            if !self.pages[level].contains_key(&page) {
+                let len = Self::page_len(level);
+                let mut words = Vec::new();
+                words.try_reserve_exact(len)?;
+                words.resize(len, T::ZERO);
+                self.pages[level].insert(page, words.into_boxed_slice());
+            }
+            page >>= T::LOG_BITS;
+        }
+        Ok(())
+    }
+
+    /// Frees the pages on the path of `p0` which became empty.
+    fn release(&mut self, p0: usize) {
+        let mut page = p0 >> T::LOG_BITS;
+        for level in 0..L {
+            match self.pages[level].get(&page) {
+                Some(words) if words.iter().all(|&word| word == T::ZERO) => {
+                    self.pages[level].remove(&page);
+                }
+                _ => return,
+            }
+            page >>= T::LOG_BITS;
+        }
+    }
+
+    fn clear(&mut self) {
+        for pages in &mut self.pages {
+            pages.clear();
+        }
+    }
+
+    /// Counts the pages and their entries in the map, but not the inner
+    /// nodes of the map.
+    fn heap_size(&self) -> usize {
+        self.pages
+            .iter()
+            .enumerate()
+            .map(|(level, pages)| {
+                let page = Self::page_len(level) * size_of::<T>();
+                pages.len() * (page + size_of::<(usize, Box<[T]>)>())
+            })
+            .sum()
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> GenericPagedBitSet<T, L> {
+    /// Returns the number of allocated layer 0 pages.
+    pub fn page_count(&self) -> usize {
+        self.storage.page_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    use super::GenericPagedBitSet;
+    use {BitSetLike, BitSetNot, CapacityError, DrainableBitSet, GenericBitSet, UnsignedInteger};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn add_remove<T: UnsignedInteger>() {
+        let mut set = GenericPagedBitSet::<T>::default();
+        let max = T::MAX_EID - 1;
+        for &i in &[max, 3, 64, 1_000] {
+            assert!(!set.add(i));
+            assert!(set.add(i));
+        }
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(max));
+        assert!(!set.contains(max - 1));
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [3, 64, 1_000, max]);
+        assert_eq!(set.last(), Some(max));
+
+        assert!(set.remove(64));
+        assert!(!set.remove(64));
+        assert!(!set.remove(max - 1));
+        assert!(!set.remove(u32::MAX));
+        assert_eq!(set.len(), 3);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [3, 1_000, max]);
+        assert_eq!(set.count(), 3);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn sparse<T: UnsignedInteger>() {
+        let mut set = GenericPagedBitSet::<T>::default();
+        set.add(T::MAX_EID - 1);
+        assert_eq!(set.page_count(), 1);
+        set.add(0);
+        set.add(T::BITS as u32 * T::BITS as u32 - 1);
+        assert_eq!(set.page_count(), 2);
+
+        // Pages are freed once empty.
+        set.remove(0);
+        assert_eq!(set.page_count(), 2);
+        set.remove(T::BITS as u32 * T::BITS as u32 - 1);
+        assert_eq!(set.page_count(), 1);
+        assert!(set.heap_size() < (T::MAX_EID as usize / 8) / 4);
+        set.clear();
+        assert_eq!(set.page_count(), 0);
+        assert!(set.is_empty());
+        assert_eq!(set.first(), None);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn combine_with_bitset<T: UnsignedInteger>() {
+        let mut paged = GenericPagedBitSet::<T>::default();
+        let mut set = GenericBitSet::<T>::new();
+        for i in 0..10_000 {
+            if i % 2 == 0 {
+                paged.add(i);
+            }
+            if i % 3 == 0 {
+                set.add(i);
+            }
+        }
+        assert_eq!(
+            (&paged & &set).iter().collect::<Vec<_>>(),
+            (0..10_000).step_by(6).collect::<Vec<_>>()
+        );
+        assert!(paged.intersects(&set));
+
+        let copy: GenericPagedBitSet<T> = (0..10_000).filter(|i| i % 2 == 0).collect();
+        assert_eq!(copy, paged);
+        assert_eq!(paged.drain().count(), 5_000);
+        assert_eq!(paged.page_count(), 0);
+        assert_eq!(paged, GenericPagedBitSet::default());
+    }
+
+    #[test_with(u32, u64, usize)]
+    fn out_of_range<T: UnsignedInteger>() {
+        let mut set = GenericPagedBitSet::<T>::default();
+        assert_eq!(
+            set.try_add(T::MAX_EID),
+            Err(CapacityError::OutOfRange {
+                index: T::MAX_EID,
+                max: T::MAX_EID
+            })
+        );
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected index to be less then 1048576, found 1048576")]
+    fn add_out_of_range() {
+        GenericPagedBitSet::<u32>::default().add(1 << 20);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn one_high_index<T: UnsignedInteger>() {
+        let mut set = GenericPagedBitSet::<T>::default();
+        set.add(T::MAX_EID - 1);
+        // A single page per layer, whatever the index.
+        let pages = (T::BITS + 3 * 2 * T::BITS) * std::mem::size_of::<T>();
+        assert!(set.heap_size() >= pages);
+        assert!(set.heap_size() < pages + 4 * 64);
+        assert!(set.heap_size() < GenericBitSet::<T>::with_capacity(T::MAX_EID).heap_size() / 100);
+        set.remove(T::MAX_EID - 1);
+        assert_eq!(set.heap_size(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn ranges<T: UnsignedInteger>() {
+        let mut set = GenericPagedBitSet::<T>::default();
+        set.insert_range(100..10_000);
+        assert_eq!(set.len(), 9_900);
+        set.remove_range(200..9_000);
+        assert!((&set).iter().eq((100..200).chain(9_000..10_000)));
+        set.toggle_range(150..9_500);
+        assert!((&set)
+            .iter()
+            .eq((100..150).chain(200..9_000).chain(9_500..10_000)));
+        assert_eq!(set.len(), (&set).iter().count());
+
+        set.remove_range(..);
+        assert!(set.is_empty());
+        assert_eq!(set.page_count(), 0);
+        assert_eq!(set.heap_size(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn operators<T: UnsignedInteger>() {
+        let evens: GenericPagedBitSet<T> = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let thirds: GenericBitSet<T> = (0..10_000).filter(|i| i % 3 == 0).collect();
+
+        let mut and = evens.clone();
+        and &= &thirds;
+        assert!((&and).iter().eq((0..10_000).step_by(6)));
+        let mut or = evens.clone();
+        or |= &thirds;
+        assert!((&or)
+            .iter()
+            .eq((0..10_000).filter(|i| i % 2 == 0 || i % 3 == 0)));
+        let mut xor = evens.clone();
+        xor ^= &thirds;
+        assert!((&xor)
+            .iter()
+            .eq((0..10_000).filter(|i| (i % 2 == 0) != (i % 3 == 0))));
+        let mut sub = evens.clone();
+        sub -= &thirds;
+        assert!((&sub)
+            .iter()
+            .eq((0..10_000).filter(|i| i % 2 == 0 && i % 3 != 0)));
+        for set in &[&and, &or, &xor, &sub] {
+            assert_eq!(set.len(), set.iter().count());
+        }
+
+        // The pages emptied by an operator are freed.
+        sub -= &evens;
+        assert!(sub.is_empty());
+        assert_eq!(sub.page_count(), 0);
+        and &= &BitSetNot(&thirds);
+        assert_eq!(and.page_count(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn full_summaries<T: UnsignedInteger>() {
+        let block = T::BITS as u32 * T::BITS as u32;
+        let mut paged = GenericPagedBitSet::<T>::default();
+        let mut set = GenericBitSet::<T>::new();
+        paged.insert_range(0..3 * block + 5);
+        set.insert_range(0..3 * block + 5);
+        paged.remove(block + 7);
+        set.remove(block + 7);
+        for level in 1..4 {
+            for idx in 0..4 {
+                assert_eq!(
+                    paged.get_full_from_layer(level, idx),
+                    set.get_full_from_layer(level, idx)
+                );
+            }
+        }
+        assert_eq!(BitSetNot(&paged).first(), Some(block + 7));
+        assert!(BitSetNot(&BitSetNot(&paged)).iter().eq((&set).iter()));
+    }
+
+    fn hash_of<H: Hash>(value: &H) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn hash_ord<T: UnsignedInteger + Hash>() {
+        let mut a: GenericPagedBitSet<T> = [3, 64, 5_000].iter().collect();
+        let b = a.clone();
+        a.add(90_000);
+        assert!(a > b);
+        a.remove(90_000);
+        assert_eq!(a, b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+        a.add(4);
+        assert!(a < b);
+        assert_ne!(hash_of(&a), hash_of(&b));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn bytes<T: UnsignedInteger>() {
+        let set: GenericPagedBitSet<T> = [1, 64, T::MAX_EID - 1].iter().collect();
+        let bytes = set.to_bytes();
+        assert_eq!(bytes, GenericBitSet::<T>::from_bitset_like(&set).to_bytes());
+        assert_eq!(GenericPagedBitSet::<T>::from_bytes(&bytes), Ok(set));
+    }
+}
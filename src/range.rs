@@ -0,0 +1,330 @@
+use std::marker::PhantomData;
+use std::ops::{RangeBounds, RangeInclusive};
+
+use util::*;
+use BitSetLike;
+
+/// Returns the words of the summary and of the full block summary of the
+/// indices `start..=end`, corresponding to layer and index.
+///
+/// A bit of `layer` stands for a block of `T::BITS**layer` indices, which
+/// is in the summary if it overlaps the range, and in the full block
+/// summary if it is within the range.
+fn range_words<T: UnsignedInteger>(start: u64, end: u64, layer: usize, idx: usize) -> (T, T) {
+    let shift = T::LOG_BITS * layer;
+    let block = 1u64 << shift;
+    let (full_start, full_end) = ((start + block - 1) >> shift, (end + 1) >> shift);
+    (
+        block_mask(idx, start >> shift, (end >> shift) + 1),
+        block_mask(idx, full_start, full_end),
+    )
+}
+
+/// Returns the bits of the word at `idx` for the blocks `start..end`.
+fn block_mask<T: UnsignedInteger>(idx: usize, start: u64, end: u64) -> T {
+    let first = (idx as u64) << T::LOG_BITS;
+    let last = first + T::BITS as u64;
+    if start >= end || end <= first || start >= last {
+        return T::ZERO;
+    }
+    let from = start.saturating_sub(first) as u32;
+    let to = (end.min(last) - first) as u32 - 1;
+    (T::MAX << T::from_u32(from)) & (T::MAX >> T::from_u32(T::BITS as u32 - 1 - to))
+}
+
+/// Converts `range` to an inclusive one, clipped to the indices a set of
+/// `L` layers of `T` can hold.
+fn clipped_range<T: UnsignedInteger, R: RangeBounds<Index>>(
+    range: R,
+    layers: usize,
+) -> RangeInclusive<Index> {
+    let range = inclusive_range(range);
+    let max = (capacity::<T>(layers) - 1) as Index;
+    *range.start()..=(*range.end()).min(max)
+}
+
+/// `BitSetRange` is a bitset holding every index within a range, which
+/// computes its words instead of storing them.
+///
+/// Intersecting it with another set clips that set to the range without
+/// any allocation, and the summary layers of both sets prune the words
+/// outside of it.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAnd, BitSetLike, BitSetRange};
+///
+/// let entities: BitSet = (0..1_000).filter(|i| i % 100 == 0).collect();
+/// let window = BitSetAnd(&entities, BitSetRange::new(250..600));
+/// assert_eq!(window.iter().collect::<Vec<_>>(), vec![300, 400, 500]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BitSetRange<T: UnsignedInteger, const L: usize = LAYERS> {
+    range: RangeInclusive<Index>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: UnsignedInteger, const L: usize> BitSetRange<T, L> {
+    /// Creates the set of the indices within `range`.
+    ///
+    /// The indices a set of `L` layers of `T` can't hold are left out.
+    pub fn new<R: RangeBounds<Index>>(range: R) -> Self {
+        BitSetRange {
+            range: clipped_range::<T, R>(range, L),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the indices of the set, as an inclusive range.
+    pub fn range(&self) -> RangeInclusive<Index> {
+        self.range.clone()
+    }
+
+    /// Returns the words of both summaries corresponding to layer and index.
+    #[inline]
+    fn get_words(&self, layer: usize, idx: usize) -> (T, T) {
+        if self.range.is_empty() {
+            return (T::ZERO, T::ZERO);
+        }
+        let (start, end) = (*self.range.start() as u64, *self.range.end() as u64);
+        range_words(start, end, layer, idx)
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> BitSetLike<L> for BitSetRange<T, L> {
+    type Underlying = T;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.get_words(layer, idx).0
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.get_words(layer, idx).1
+    }
+    #[inline]
+    fn count(&self) -> usize {
+        if self.range.is_empty() {
+            0
+        } else {
+            (*self.range.end() - *self.range.start()) as usize + 1
+        }
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.range.contains(&i)
+    }
+}
+
+/// `BitSetIntervals` is a bitset holding every index within a list of
+/// ranges, which computes its words instead of storing them.
+///
+/// The ranges are kept sorted and merged, and the ones overlapping a word
+/// are found by binary search, so only the ranges themselves are
+/// allocated.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSetIntervals, BitSetLike};
+///
+/// let intervals = BitSetIntervals::<usize>::new(vec![2..4, 10..12, 3..6]);
+/// assert_eq!(intervals.iter().collect::<Vec<_>>(), vec![2, 3, 4, 5, 10, 11]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BitSetIntervals<T: UnsignedInteger, const L: usize = LAYERS> {
+    ranges: Vec<RangeInclusive<Index>>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: UnsignedInteger, const L: usize> BitSetIntervals<T, L> {
+    /// Creates the set of the indices within any of `ranges`.
+    ///
+    /// Empty ranges are skipped, and the others are sorted and merged
+    /// when they overlap or touch. The indices a set of `L` layers of `T`
+    /// can't hold are left out.
+    pub fn new<I, R>(ranges: I) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: RangeBounds<Index>,
+    {
+        let mut sorted: Vec<_> = ranges
+            .into_iter()
+            .map(|range| clipped_range::<T, R>(range, L))
+            .filter(|range| !range.is_empty())
+            .collect();
+        sorted.sort_by_key(|range| *range.start());
+
+        let mut merged: Vec<RangeInclusive<Index>> = Vec::with_capacity(sorted.len());
+        for range in sorted {
+            match merged.last_mut() {
+                Some(last) if *range.start() as u64 <= *last.end() as u64 + 1 => {
+                    let end = (*last.end()).max(*range.end());
+                    *last = *last.start()..=end;
+                }
+                _ => merged.push(range),
+            }
+        }
+        BitSetIntervals {
+            ranges: merged,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the sorted and merged ranges of the set.
+    pub fn ranges(&self) -> &[RangeInclusive<Index>] {
+        &self.ranges
+    }
+
+    /// Returns the words of both summaries from the ranges overlapping the
+    /// word at layer and index.
+    fn get_words(&self, layer: usize, idx: usize) -> (T, T) {
+        let shift = T::LOG_BITS * (layer + 1);
+        let first = (idx as u64) << shift;
+        let last = first + (1 << shift) - 1;
+        let from = self
+            .ranges
+            .partition_point(|range| (*range.end() as u64) < first);
+        self.ranges[from..]
+            .iter()
+            .take_while(|range| *range.start() as u64 <= last)
+            .fold((T::ZERO, T::ZERO), |(words, full), range| {
+                let (start, end) = (*range.start() as u64, *range.end() as u64);
+                let (range_words, range_full) = range_words::<T>(start, end, layer, idx);
+                (words | range_words, full | range_full)
+            })
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> BitSetLike<L> for BitSetIntervals<T, L> {
+    type Underlying = T;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.get_words(layer, idx).0
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.get_words(layer, idx).1
+    }
+    #[inline]
+    fn count(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|range| (*range.end() - *range.start()) as usize + 1)
+            .sum()
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        let at = self.ranges.partition_point(|range| *range.end() < i);
+        self.ranges
+            .get(at)
+            .map(|range| range.contains(&i))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use super::{BitSetIntervals, BitSetRange};
+    use {BitSetAnd, BitSetLike, BitSetNot, GenericBitSet, Index, UnsignedInteger};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn range_matches_bitset<T: UnsignedInteger>() {
+        let bits = T::BITS as Index;
+        for &(start, end) in &[
+            (0, 1),
+            (3, 5),
+            (0, bits),
+            (bits - 1, bits * bits + 1),
+            (5, 3 * bits * bits * bits + 7),
+            (10, 10),
+        ] {
+            let range = BitSetRange::<T>::new(start..end);
+            let mut set = GenericBitSet::<T>::new();
+            set.insert_range(start..end);
+            assert!(range.set_eq(&set));
+            assert_eq!(range.count(), set.len());
+            for layer in 0..4 {
+                for idx in 0..4 {
+                    assert_eq!(
+                        range.get_from_layer(layer, idx),
+                        set.get_from_layer(layer, idx)
+                    );
+                    assert_eq!(
+                        range.get_full_from_layer(layer, idx),
+                        set.get_full_from_layer(layer, idx)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn range_clips<T: UnsignedInteger>() {
+        let entities: GenericBitSet<T> = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let window = BitSetAnd(&entities, BitSetRange::new(1_000..=2_000));
+        assert_eq!(
+            window.iter().collect::<Vec<_>>(),
+            (1_002..=2_000).step_by(3).collect::<Vec<_>>()
+        );
+
+        let all = BitSetRange::<T>::new(..);
+        assert_eq!(all.range(), 0..=T::MAX_EID - 1);
+        assert_eq!(all.count() as Index, T::MAX_EID);
+        assert!(!all.contains(T::MAX_EID));
+        assert!(BitSetNot(&all).is_empty());
+        assert!(BitSetRange::<T>::new(5..5).is_empty());
+        assert_eq!(
+            BitSetNot(BitSetRange::<T>::new(..50_000)).first(),
+            Some(50_000)
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn intervals<T: UnsignedInteger>() {
+        let bits = T::BITS as Index;
+        let ranges = [
+            bits * bits..3 * bits * bits,
+            5..7,
+            0..2,
+            6..bits + 1,
+            70_000..70_000,
+            100_000..100_003,
+        ];
+        let intervals = BitSetIntervals::<T>::new(ranges.iter().cloned());
+        assert_eq!(
+            intervals.ranges(),
+            &[
+                0..=1,
+                5..=bits,
+                bits * bits..=3 * bits * bits - 1,
+                100_000..=100_002
+            ]
+        );
+
+        let mut set = GenericBitSet::<T>::new();
+        for range in ranges.iter().cloned() {
+            set.insert_range(range);
+        }
+        assert!(intervals.set_eq(&set));
+        assert_eq!(intervals.count(), set.len());
+        assert!(BitSetNot(&intervals).set_eq(&BitSetNot(&set)));
+        assert_eq!(
+            intervals.get_full_from_layer(1, 1),
+            set.get_full_from_layer(1, 1)
+        );
+        assert_eq!(
+            intervals.get_full_from_layer(2, 0),
+            set.get_full_from_layer(2, 0)
+        );
+        assert!(intervals.contains(100_002));
+        assert!(!intervals.contains(100_003));
+        assert!(!intervals.contains(2));
+        assert!(BitSetIntervals::<T>::new(Vec::<std::ops::Range<Index>>::new()).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! `serde` support for `GenericBitSet`, whatever its storage, and
+//! `GenericAtomicBitSet`.
+//!
+//! Both are written as a sequence of `(offset, word)` pairs holding the
+//! non-zero layer 0 words in increasing order. The summary layers are not
+//! written, they are rebuilt while reading the words back.
+
+use std::fmt::{Formatter, Result as FormatResult};
+use std::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use iter::WordIter;
+use storage::Storage;
+use util::*;
+use {BitSetLike, GenericAtomicBitSet, GenericBitSet};
+
+fn serialize_words<S, B, const L: usize>(set: B, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    B: BitSetLike<L>,
+    B::Underlying: Serialize,
+{
+    // The words are read once, so the length matches the elements even if
+    // an atomic set is added to meanwhile.
+    let words: Vec<_> = WordIter::<B, L>::new(set).collect();
+    let mut seq = serializer.serialize_seq(Some(words.len()))?;
+    for (offset, word) in words {
+        seq.serialize_element(&(offset as u32, word))?;
+    }
+    seq.end()
+}
+
+/// Reads `(offset, word)` pairs, passing each non-zero word to `insert`.
+struct WordVisitor<T, F, const L: usize> {
+    insert: F,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T, F, const L: usize> Visitor<'de> for WordVisitor<T, F, L>
+where
+    T: UnsignedInteger + Deserialize<'de>,
+    F: FnMut(usize, T),
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> FormatResult {
+        formatter.write_str("a sequence of (offset, word) pairs")
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let max = capacity::<T>(L) >> T::LOG_BITS;
+        while let Some((offset, word)) = seq.next_element::<(u32, T)>()? {
+            if offset as u64 >= max {
+                return Err(Error::invalid_value(
+                    Unexpected::Unsigned(offset as u64),
+                    &&*format!("a word offset less than {}", max),
+                ));
+            }
+            (self.insert)(offset as usize, word);
+        }
+        Ok(())
+    }
+}
+
+impl<T, const L: usize, M> Serialize for GenericBitSet<T, L, M>
+where
+    T: UnsignedInteger + Serialize,
+    M: Storage<T, L>,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_words(self, serializer)
+    }
+}
+
+impl<'de, T, const L: usize, M> Deserialize<'de> for GenericBitSet<T, L, M>
+where
+    T: UnsignedInteger + Deserialize<'de>,
+    M: Storage<T, L>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut set = Self::default();
+        deserializer.deserialize_seq(WordVisitor::<_, _, L> {
+            insert: |offset, word| set.insert_word(offset, word),
+            marker: PhantomData,
+        })?;
+        Ok(set)
+    }
+}
+
+impl<T> Serialize for GenericAtomicBitSet<T>
+where
+    T: AtomicUnsignedInteger + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_words(self, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for GenericAtomicBitSet<T>
+where
+    T: AtomicUnsignedInteger + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut set = GenericAtomicBitSet::new();
+        deserializer.deserialize_seq(WordVisitor::<_, _, LAYERS> {
+            insert: |offset, word| set.insert_word(offset, word),
+            marker: PhantomData,
+        })?;
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json;
+
+    use {AtomicBitSet, BitSetLike, GenericBitSet, GenericPagedBitSet, UnsignedInteger};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn round_trip<T: UnsignedInteger + Serialize + DeserializeOwned>() {
+        let mut set = GenericBitSet::<T>::new();
+        for i in (0..10_000).filter(|i| i % 7 == 0).chain(500_000..500_100) {
+            set.add(i);
+        }
+        let json = serde_json::to_string(&set).unwrap();
+        let read: GenericBitSet<T> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.len(), set.len());
+        assert_eq!(
+            (&read).iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(read.last(), Some(500_099));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn paged<T: UnsignedInteger + Serialize + DeserializeOwned>() {
+        let set: GenericPagedBitSet<T> = [3, 64, T::MAX_EID - 1].iter().collect();
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(
+            json,
+            serde_json::to_string(&GenericBitSet::<T>::from_bitset_like(&set)).unwrap()
+        );
+        let read: GenericPagedBitSet<T> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, set);
+        assert_eq!(read.page_count(), 2);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn sparse_words<T: UnsignedInteger + Serialize + DeserializeOwned>() {
+        let mut set = GenericBitSet::<T>::new();
+        set.add(3);
+        set.add(T::BITS as u32 * 5 + 1);
+        assert_eq!(
+            serde_json::to_string(&set).unwrap(),
+            "[[0,8],[5,2]]".to_owned()
+        );
+        assert_eq!(
+            serde_json::to_string(&GenericBitSet::<T>::new()).unwrap(),
+            "[]"
+        );
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn zero_and_repeated_words<T: UnsignedInteger + Serialize + DeserializeOwned>() {
+        let set: GenericBitSet<T> = serde_json::from_str("[[2,0],[1,1],[1,4]]").unwrap();
+        let bits = T::BITS as u32;
+        assert_eq!((&set).iter().collect::<Vec<_>>(), vec![bits, bits + 2]);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn reject_out_of_range<T: UnsignedInteger + Serialize + DeserializeOwned>() {
+        let max = T::MAX_EID >> T::LOG_BITS;
+        let json = format!("[[{},1]]", max);
+        assert!(serde_json::from_str::<GenericBitSet<T>>(&json).is_err());
+        let json = format!("[[{},1]]", max - 1);
+        let set: GenericBitSet<T> = serde_json::from_str(&json).unwrap();
+        assert_eq!(set.first(), Some((max - 1) << T::LOG_BITS));
+    }
+
+    #[test]
+    fn atomic_round_trip() {
+        let set = AtomicBitSet::new();
+        for i in (0..10_000)
+            .filter(|i| i % 5 == 0)
+            .chain(1_000_000..1_000_050)
+        {
+            set.add_atomic(i);
+        }
+        let json = serde_json::to_string(&set).unwrap();
+        let read: AtomicBitSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            (&read).iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+
+        let plain: GenericBitSet<usize> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            plain.iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn atomic_reject_out_of_range() {
+        let max = usize::MAX_EID >> usize::LOG_BITS;
+        let json = format!("[[{},1]]", max);
+        assert!(serde_json::from_str::<AtomicBitSet>(&json).is_err());
+    }
+}
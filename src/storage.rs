@@ -0,0 +1,226 @@
+use std::fmt::Debug;
+use std::mem::size_of;
+
+use util::*;
+use CapacityError;
+
+/// How the words of the layers of a bit set are stored.
+///
+/// Implementors only read and write single words, while the provided
+/// methods keep the summary layers and the full block summaries in sync
+/// with layer 0, so every storage shares the same hierarchy logic.
+///
+/// Words are addressed by their layer and their offset in it. Reading a
+/// word which was never written returns zero.
+pub trait Storage<T: UnsignedInteger, const L: usize>: Clone + Debug + Default {
+    /// Returns the word at offset `idx` of layer `level`.
+    fn word(&self, level: usize, idx: usize) -> T;
+
+    /// Returns the full block summary word at offset `idx` of layer
+    /// `level`, which is above 0.
+    fn full(&self, level: usize, idx: usize) -> T;
+
+    /// Returns the word at offset `idx` of layer `level`, allocating it.
+    fn word_mut(&mut self, level: usize, idx: usize) -> &mut T;
+
+    /// Returns the full block summary word at offset `idx` of layer
+    /// `level`, which is above 0, allocating it.
+    fn full_mut(&mut self, level: usize, idx: usize) -> &mut T;
+
+    /// Allocates every word the layer 0 word at offset `p0` and its
+    /// summaries need, so writing them doesn't allocate.
+    fn try_reserve(&mut self, p0: usize) -> Result<(), CapacityError>;
+
+    /// Called once the layer 0 word at offset `p0` became zero and its
+    /// summaries were updated, to free the memory it no longer needs.
+    #[inline]
+    fn release(&mut self, _p0: usize) {}
+
+    /// Frees every word.
+    fn clear(&mut self);
+
+    /// Returns the number of bytes allocated on the heap.
+    fn heap_size(&self) -> usize;
+
+    /// Replaces the layer 0 word at offset `p0` with `word`, updating the
+    /// summary layers and the full block summaries, and returns the
+    /// previous word.
+    #[inline]
+    fn set_word(&mut self, p0: usize, word: T) -> T {
+        let old = self.word(0, p0);
+        if old == word {
+            return old;
+        }
+        *self.word_mut(0, p0) = word;
+        if old == T::MAX {
+            self.unset_full(p0);
+        } else if word == T::MAX {
+            self.set_full(p0);
+        }
+        if old == T::ZERO {
+            self.add_slow(p0);
+        } else if word == T::ZERO {
+            self.remove_slow(p0);
+            self.release(p0);
+        }
+        old
+    }
+
+    /// This is used to set the levels in the hierarchy
+    /// when the layer 0 word `p0` was set from 0.
+    #[inline(never)]
+    fn add_slow(&mut self, p0: usize) {
+        let mut idx = p0;
+        for level in 1..L {
+            let (word, bit) = (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1));
+            *self.word_mut(level, word) |= T::ONE << T::from_u32(bit as u32);
+            idx = word;
+        }
+    }
+
+    /// This is used to clear the levels in the hierarchy
+    /// when the layer 0 word `p0` was cleared to 0.
+    #[inline(never)]
+    fn remove_slow(&mut self, p0: usize) {
+        let mut idx = p0;
+        for level in 1..L {
+            let (word, bit) = (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1));
+            let word_mut = self.word_mut(level, word);
+            *word_mut &= !(T::ONE << T::from_u32(bit as u32));
+            if *word_mut != T::ZERO {
+                return;
+            }
+            idx = word;
+        }
+    }
+
+    /// This is used to mark the layer 0 word `p0` in the full block
+    /// summaries when it became full.
+    #[inline(never)]
+    fn set_full(&mut self, p0: usize) {
+        let mut idx = p0;
+        for level in 1..L {
+            let (word, bit) = (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1));
+            let full = self.full_mut(level, word);
+            *full |= T::ONE << T::from_u32(bit as u32);
+            if *full != T::MAX {
+                return;
+            }
+            idx = word;
+        }
+    }
+
+    /// This is used to unmark the layer 0 word `p0` in the full block
+    /// summaries when it stopped being full.
+    #[inline(never)]
+    fn unset_full(&mut self, p0: usize) {
+        let mut idx = p0;
+        for level in 1..L {
+            let (word, bit) = (idx >> T::LOG_BITS, idx & ((1 << T::LOG_BITS) - 1));
+            let full = self.full_mut(level, word);
+            let was_full = *full == T::MAX;
+            *full &= !(T::ONE << T::from_u32(bit as u32));
+            if !was_full {
+                return;
+            }
+            idx = word;
+        }
+    }
+}
+
+/// The default storage of a [`GenericBitSet`], where every layer is a
+/// single vector growing with the largest index of the set.
+///
+/// [`GenericBitSet`]: ../struct.GenericBitSet.html
+#[derive(Clone, Debug)]
+pub struct DenseStorage<T: UnsignedInteger, const L: usize> {
+    pub(crate) layers: [Vec<T>; L],
+    /// The full block summaries of the layers above 0, `full[0]` is unused.
+    pub(crate) full: [Vec<T>; L],
+}
+
+impl<T: UnsignedInteger, const L: usize> Default for DenseStorage<T, L> {
+    fn default() -> Self {
+        DenseStorage {
+            layers: std::array::from_fn(|_| Vec::new()),
+            full: std::array::from_fn(|_| Vec::new()),
+        }
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> DenseStorage<T, L> {
+    fn fill_up(vec: &mut Vec<T>, idx: usize) -> &mut T {
+        if vec.len() <= idx {
+            vec.resize(idx + 1, T::ZERO);
+        }
+        &mut vec[idx]
+    }
+
+    fn try_reserve_layer(vec: &mut Vec<T>, len: usize) -> Result<(), CapacityError> {
+        if vec.len() < len {
+            vec.try_reserve(len - vec.len())?;
+        }
+        Ok(())
+    }
+
+    /// Grows every layer to hold the layer 0 word `p0`, reporting
+    /// allocation failures.
+    pub(crate) fn try_grow(&mut self, p0: usize) -> Result<(), CapacityError> {
+        self.try_reserve(p0)?;
+        for level in (0..L).rev() {
+            let idx = p0 >> (T::LOG_BITS * level);
+            Self::fill_up(&mut self.layers[level], idx);
+            if level > 0 {
+                Self::fill_up(&mut self.full[level], idx);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> Storage<T, L> for DenseStorage<T, L> {
+    #[inline]
+    fn word(&self, level: usize, idx: usize) -> T {
+        self.layers[level].get(idx).copied().unwrap_or(T::ZERO)
+    }
+
+    #[inline]
+    fn full(&self, level: usize, idx: usize) -> T {
+        self.full[level].get(idx).copied().unwrap_or(T::ZERO)
+    }
+
+    #[inline]
+    fn word_mut(&mut self, level: usize, idx: usize) -> &mut T {
+        Self::fill_up(&mut self.layers[level], idx)
+    }
+
+    #[inline]
+    fn full_mut(&mut self, level: usize, idx: usize) -> &mut T {
+        Self::fill_up(&mut self.full[level], idx)
+    }
+
+    fn try_reserve(&mut self, p0: usize) -> Result<(), CapacityError> {
+        for level in 0..L {
+            let len = (p0 >> (T::LOG_BITS * level)) + 1;
+            Self::try_reserve_layer(&mut self.layers[level], len)?;
+            if level > 0 {
+                Self::try_reserve_layer(&mut self.full[level], len)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        for layer in self.layers.iter_mut().chain(&mut self.full) {
+            layer.clear();
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.layers
+            .iter()
+            .chain(&self.full)
+            .map(|layer| layer.capacity() * size_of::<T>())
+            .sum()
+    }
+}
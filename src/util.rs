@@ -1,4 +1,9 @@
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXorAssign, Div, Not, Shl, Shr, Sub};
+use std::fmt::Debug;
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Bound, Div, Not, RangeBounds,
+    RangeInclusive, Shl, Shr, Sub,
+};
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 
 /// Type used for indexing.
 pub type Index = u32;
@@ -6,8 +11,11 @@ pub type Index = u32;
 /// helper function to get the base 2 log of a const number
 const fn base_2_log<const N: usize>() -> usize {
     match N {
+        8 => 3,
+        16 => 4,
         32 => 5,
         64 => 6,
+        128 => 7,
         _ => unimplemented!(),
     }
 }
@@ -18,13 +26,14 @@ pub trait UnsignedInteger:
     + Clone
     + Copy
     + Default
-    + std::fmt::Debug
+    + Debug
     + PartialEq
     + Not<Output = Self>
     + BitAnd<Output = Self>
     + BitAndAssign
     + BitOr<Output = Self>
     + BitOrAssign
+    + BitXor<Output = Self>
     + BitXorAssign
     + Shl<Output = Self>
     + Shr<Output = Self>
@@ -41,8 +50,12 @@ pub trait UnsignedInteger:
     const BITS: usize;
     /// Base two log of the number of bits.
     const LOG_BITS: usize;
+    /// Number of bits per word in the byte encoding, which is 64 for `usize`
+    /// so that the encoding is the same on every platform. It is either
+    /// `BITS` or 64.
+    const ENCODED_BITS: usize = Self::BITS;
     /// Maximum amount of bits per bitset.
-    const MAX_EID: u32 = (2 << (Self::LOG_BITS * LAYERS) - 1) as u32;
+    const MAX_EID: u32 = (2 << ((Self::LOG_BITS * LAYERS) - 1)) as u32;
     /// Layer0 shift (bottom layer, true bitset).
     const SHIFT0: usize = 0;
     /// Layer1 shift (third layer).
@@ -62,16 +75,24 @@ pub trait UnsignedInteger:
     fn to_u64(self) -> u64;
     /// Returns the number of trailing zeros in the binary representation of self.
     fn trailing_zeros(self) -> u32;
+    /// Returns the number of ones in the binary representation of self.
+    fn count_ones(self) -> u32;
+    /// Returns the number of leading zeros in the binary representation of self.
+    fn leading_zeros(self) -> u32;
 }
 
 macro_rules! from_primitive_uint {
     ($type:ident) => {
+        from_primitive_uint!($type, Self::BITS as usize);
+    };
+    ($type:ident, $encoded_bits:expr) => {
         impl UnsignedInteger for $type {
             const ZERO: Self = 0;
             const ONE: Self = 1;
             const MAX: Self = Self::MAX;
             const BITS: usize = Self::BITS as usize;
             const LOG_BITS: usize = base_2_log::<{ Self::BITS as usize }>();
+            const ENCODED_BITS: usize = $encoded_bits;
             #[inline(always)]
             fn from_u32(val: u32) -> Self {
                 val as Self
@@ -92,15 +113,80 @@ macro_rules! from_primitive_uint {
             fn trailing_zeros(self) -> u32 {
                 self.trailing_zeros()
             }
+            #[inline(always)]
+            fn count_ones(self) -> u32 {
+                self.count_ones()
+            }
+            #[inline(always)]
+            fn leading_zeros(self) -> u32 {
+                self.leading_zeros()
+            }
         }
     };
 }
 
-from_primitive_uint!(usize);
+from_primitive_uint!(usize, 64);
+from_primitive_uint!(u128);
 from_primitive_uint!(u64);
 from_primitive_uint!(u32);
+from_primitive_uint!(u16);
+from_primitive_uint!(u8);
+
+/// Specifies the atomic counterpart of an `UnsignedInteger`, necessary for
+/// a `GenericAtomicBitSet` to be built on top of `Self`
+pub trait AtomicUnsignedInteger: UnsignedInteger {
+    /// Atomic type holding a `Self`
+    type Atomic: Send + Sync + Debug;
+
+    /// Creates a new atomic holding `val`.
+    fn new_atomic(val: Self) -> Self::Atomic;
+    /// Loads the value of `atomic`.
+    fn load(atomic: &Self::Atomic, order: Ordering) -> Self;
+    /// Bitwise or of `atomic` with `val`, returning the previous value.
+    fn fetch_or(atomic: &Self::Atomic, val: Self, order: Ordering) -> Self;
+    /// Stores `val` into `atomic`, returning the previous value.
+    fn swap(atomic: &Self::Atomic, val: Self, order: Ordering) -> Self;
+    /// Returns a mutable reference to the value of `atomic`.
+    fn get_mut(atomic: &mut Self::Atomic) -> &mut Self;
+}
+
+macro_rules! from_primitive_atomic {
+    ($type:ident, $atomic:ident, $width:tt) => {
+        #[cfg(target_has_atomic = $width)]
+        impl AtomicUnsignedInteger for $type {
+            type Atomic = $atomic;
+
+            #[inline(always)]
+            fn new_atomic(val: Self) -> $atomic {
+                $atomic::new(val)
+            }
+            #[inline(always)]
+            fn load(atomic: &$atomic, order: Ordering) -> Self {
+                atomic.load(order)
+            }
+            #[inline(always)]
+            fn fetch_or(atomic: &$atomic, val: Self, order: Ordering) -> Self {
+                atomic.fetch_or(val, order)
+            }
+            #[inline(always)]
+            fn swap(atomic: &$atomic, val: Self, order: Ordering) -> Self {
+                atomic.swap(val, order)
+            }
+            #[inline(always)]
+            fn get_mut(atomic: &mut $atomic) -> &mut Self {
+                atomic.get_mut()
+            }
+        }
+    };
+}
+
+from_primitive_atomic!(usize, AtomicUsize, "ptr");
+from_primitive_atomic!(u64, AtomicU64, "64");
+from_primitive_atomic!(u32, AtomicU32, "32");
+from_primitive_atomic!(u16, AtomicU16, "16");
+from_primitive_atomic!(u8, AtomicU8, "8");
 
-/// Amount of layers in the hierarchical bitset.
+/// Default amount of layers in the hierarchical bitset.
 pub const LAYERS: usize = 4;
 
 pub trait Row: Sized + Copy {
@@ -120,12 +206,115 @@ pub trait Row: Sized + Copy {
 impl Row for Index {
     #[inline(always)]
     fn row<T: UnsignedInteger>(self, shift: usize) -> T {
-        T::from_u32(self >> shift) & T::from_u32((1 << T::LOG_BITS) - 1)
+        T::from_u32(self.checked_shr(shift as u32).unwrap_or(0))
+            & T::from_u32((1 << T::LOG_BITS) - 1)
     }
 
     #[inline(always)]
     fn offset(self, shift: usize) -> usize {
-        self as usize / (1 << shift)
+        self.checked_shr(shift as u32).unwrap_or(0) as usize
+    }
+}
+
+impl Row for u64 {
+    #[inline(always)]
+    fn row<T: UnsignedInteger>(self, shift: usize) -> T {
+        T::from_u64(self.checked_shr(shift as u32).unwrap_or(0))
+            & T::from_u32((1 << T::LOG_BITS) - 1)
+    }
+
+    #[inline(always)]
+    fn offset(self, shift: usize) -> usize {
+        self.checked_shr(shift as u32).unwrap_or(0) as usize
+    }
+}
+
+/// Returns how many indices a set made of `layers` layers of `T` can hold.
+///
+/// This is `T::BITS ** layers`, capped to the number of distinct `Index` values.
+pub const fn capacity<T: UnsignedInteger>(layers: usize) -> u64 {
+    let bits = T::LOG_BITS * layers;
+    if bits < Index::BITS as usize {
+        1 << bits
+    } else {
+        1 << Index::BITS
+    }
+}
+
+/// Returns the bits of the top layer of a set made of `layers` layers of `T`
+/// which cover indices below [`capacity`].
+pub fn top_mask<T: UnsignedInteger>(layers: usize) -> T {
+    let shift = T::LOG_BITS * (layers - 1);
+    let bits = capacity::<T>(layers).checked_shr(shift as u32).unwrap_or(0);
+    if bits >= T::BITS as u64 {
+        T::MAX
+    } else {
+        (T::ONE << T::from_u64(bits)) - T::ONE
+    }
+}
+
+/// Returns the largest `u64` index a set made of `layers` layers of `T` can hold.
+///
+/// This is `T::BITS ** layers - 1`, capped to `u64::MAX`.
+pub const fn wide_max_index<T: UnsignedInteger>(layers: usize) -> u64 {
+    let bits = T::LOG_BITS * layers;
+    if bits < u64::BITS as usize {
+        (1 << bits) - 1
+    } else {
+        u64::MAX
+    }
+}
+
+/// Returns the bits of the top layer of a set made of `layers` layers of `T`
+/// which cover `u64` indices, see [`wide_max_index`].
+pub fn wide_top_mask<T: UnsignedInteger>(layers: usize) -> T {
+    // The bits of an index left for the top layer
+    let spare = u64::BITS as usize - T::LOG_BITS * (layers - 1);
+    if spare >= T::LOG_BITS {
+        T::MAX
+    } else {
+        (T::ONE << T::from_u32(1 << spare)) - T::ONE
+    }
+}
+
+/// Converts any range of indices to an inclusive one.
+///
+/// Empty ranges are returned as `1..=0`.
+pub fn inclusive_range<R: RangeBounds<Index>>(range: R) -> RangeInclusive<Index> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => Some(end),
+        Bound::Excluded(&end) => end.checked_sub(1),
+        Bound::Unbounded => Some(Index::MAX),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) => start..=end,
+        _ => RangeInclusive::new(1, 0),
+    }
+}
+
+/// Converts any range of `u64` indices to an inclusive one, ending
+/// unbounded ranges at `max`.
+///
+/// Empty ranges are returned as `1..=0`.
+pub fn inclusive_wide_range<R: RangeBounds<u64>>(range: R, max: u64) -> RangeInclusive<u64> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => Some(end),
+        Bound::Excluded(&end) => end.checked_sub(1),
+        Bound::Unbounded => Some(max),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) => start..=end,
+        _ => RangeInclusive::new(1, 0),
     }
 }
 
@@ -141,6 +330,13 @@ pub fn offsets<T: UnsignedInteger>(bit: Index) -> (usize, usize, usize) {
     )
 }
 
+/// Index of the layer 0 word holding `bit` within the block of `T::BITS`
+/// words summarized by its layer 1 word.
+#[inline(always)]
+pub fn block_index<T: UnsignedInteger>(bit: Index) -> usize {
+    bit.offset(T::SHIFT1) & ((1 << T::LOG_BITS) - 1)
+}
+
 /// Finds the highest bit that splits set bits of the `usize`
 /// to half (rounding up).
 ///
@@ -159,13 +355,32 @@ pub fn offsets<T: UnsignedInteger>(bit: Index) -> (usize, usize, usize) {
 // Seems that this would need integer generics to do.
 #[cfg(feature = "parallel")]
 pub fn average_ones<T: UnsignedInteger>(n: T) -> Option<T> {
-    let average = match T::BITS {
+    match T::BITS {
         32 => average_ones_u32(n.to_u32()).map(T::from_u32),
         64 => average_ones_u64(n.to_u64()).map(T::from_u64),
-        _ => unimplemented!(),
-    };
+        _ => average_ones_generic(n),
+    }
+}
 
-    average
+/// `average_ones` for the word sizes without a parallel bit counting variant.
+///
+/// Binary searches the highest bit such that the bits at and above it hold
+/// half of the set bits (rounding down).
+#[cfg(feature = "parallel")]
+fn average_ones_generic<T: UnsignedInteger>(n: T) -> Option<T> {
+    let count = n.count_ones();
+    if count <= 1 {
+        return None;
+    }
+    let upper = count / 2;
+    let mut result = 0;
+    for step in (0..T::LOG_BITS).rev() {
+        let candidate = result | (1 << step);
+        if (n >> T::from_u32(candidate)).count_ones() >= upper {
+            result = candidate;
+        }
+    }
+    Some(T::from_u32(result))
 }
 
 #[cfg(feature = "parallel")]
@@ -275,12 +490,12 @@ mod test_average_ones {
         impl Iterator for EvenParity {
             type Item = u32;
             fn next(&mut self) -> Option<Self::Item> {
-                if self.0 == u32::max_value() {
+                if self.0 == u32::MAX {
                     return None;
                 }
                 self.0 += 1;
                 while self.0.count_ones() & 1 != 0 {
-                    if self.0 == u32::max_value() {
+                    if self.0 == u32::MAX {
                         return None;
                     }
                     self.0 += 1;
@@ -291,7 +506,7 @@ mod test_average_ones {
 
         let steps = 1000;
         for i in 0..steps {
-            let pos = i * (u32::max_value() / steps);
+            let pos = i * (u32::MAX / steps);
             for i in EvenParity(pos).take(steps as usize) {
                 let mask = (1 << average_ones_u32(i).unwrap_or(31)) - 1;
                 assert_eq!((i & mask).count_ones(), (i & !mask).count_ones(), "{:x}", i);
@@ -306,12 +521,12 @@ mod test_average_ones {
         impl Iterator for OddParity {
             type Item = u32;
             fn next(&mut self) -> Option<Self::Item> {
-                if self.0 == u32::max_value() {
+                if self.0 == u32::MAX {
                     return None;
                 }
                 self.0 += 1;
                 while self.0.count_ones() & 1 == 0 {
-                    if self.0 == u32::max_value() {
+                    if self.0 == u32::MAX {
                         return None;
                     }
                     self.0 += 1;
@@ -322,7 +537,7 @@ mod test_average_ones {
 
         let steps = 1000;
         for i in 0..steps {
-            let pos = i * (u32::max_value() / steps);
+            let pos = i * (u32::MAX / steps);
             for i in OddParity(pos).take(steps as usize) {
                 let mask = (1 << average_ones_u32(i).unwrap_or(31)) - 1;
                 let a = (i & mask).count_ones();
@@ -357,12 +572,12 @@ mod test_average_ones {
         impl Iterator for EvenParity {
             type Item = u64;
             fn next(&mut self) -> Option<Self::Item> {
-                if self.0 == u64::max_value() {
+                if self.0 == u64::MAX {
                     return None;
                 }
                 self.0 += 1;
                 while self.0.count_ones() & 1 != 0 {
-                    if self.0 == u64::max_value() {
+                    if self.0 == u64::MAX {
                         return None;
                     }
                     self.0 += 1;
@@ -373,7 +588,7 @@ mod test_average_ones {
 
         let steps = 1000;
         for i in 0..steps {
-            let pos = i * (u64::max_value() / steps);
+            let pos = i * (u64::MAX / steps);
             for i in EvenParity(pos).take(steps as usize) {
                 let mask = (1 << average_ones_u64(i).unwrap_or(63)) - 1;
                 assert_eq!((i & mask).count_ones(), (i & !mask).count_ones(), "{:x}", i);
@@ -388,12 +603,12 @@ mod test_average_ones {
         impl Iterator for OddParity {
             type Item = u64;
             fn next(&mut self) -> Option<Self::Item> {
-                if self.0 == u64::max_value() {
+                if self.0 == u64::MAX {
                     return None;
                 }
                 self.0 += 1;
                 while self.0.count_ones() & 1 == 0 {
-                    if self.0 == u64::max_value() {
+                    if self.0 == u64::MAX {
                         return None;
                     }
                     self.0 += 1;
@@ -404,7 +619,7 @@ mod test_average_ones {
 
         let steps = 1000;
         for i in 0..steps {
-            let pos = i * (u64::max_value() / steps);
+            let pos = i * (u64::MAX / steps);
             for i in OddParity(pos).take(steps as usize) {
                 let mask = (1 << average_ones_u64(i).unwrap_or(63)) - 1;
                 let a = (i & mask).count_ones();
@@ -436,7 +651,7 @@ mod test_average_ones {
     fn average_ones_agree_u32_u64() {
         let steps = 1000;
         for i in 0..steps {
-            let pos = i * (u32::max_value() / steps);
+            let pos = i * (u32::MAX / steps);
             for i in pos..steps {
                 assert_eq!(
                     average_ones_u32(i),
@@ -459,5 +674,34 @@ mod test_average_ones {
         assert_eq!(Some(5), average_ones_u64(0b100010));
         assert_eq!(None, average_ones_u64(0));
         assert_eq!(None, average_ones_u64(1));
+
+        assert_eq!(Some(4), average_ones(0b10110u8));
+        assert_eq!(Some(5), average_ones(0b100010u16));
+        assert_eq!(Some(100), average_ones((1u128 << 100) | (1 << 3)));
+        assert_eq!(None, average_ones(0u128));
+        assert_eq!(None, average_ones(1u8 << 7));
+    }
+
+    #[test]
+    fn average_ones_agree_generic_u64() {
+        let steps = 1000;
+        for i in 0..steps {
+            let pos = i * (u64::MAX / steps);
+            for i in pos..pos + steps {
+                assert_eq!(average_ones_generic(i), average_ones_u64(i), "{:x}", i);
+                assert_eq!(
+                    average_ones_generic(i as u128),
+                    average_ones_u64(i).map(|n| n as u128),
+                    "{:x}",
+                    i
+                );
+                assert_eq!(
+                    average_ones_generic(i as u16),
+                    average_ones_u64(i & 0xffff).map(|n| n as u16),
+                    "{:x}",
+                    i
+                );
+            }
+        }
     }
 }
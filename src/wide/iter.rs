@@ -0,0 +1,352 @@
+use std::mem;
+use std::ops::RangeInclusive;
+
+use iter::State;
+use util::*;
+
+use super::{GenericWideBitSet, WideBitSetLike};
+
+/// An `Iterator` over a [`WideBitSetLike`] structure, the `u64` indexed
+/// counterpart of [`BitIter`].
+///
+/// Like `BitIter`, it keeps the word it is visiting on every layer
+/// together with the prefix of its indices, so each step only reads a
+/// layer below when it descends into a set summary bit. It can also be
+/// iterated from the back, yielding indices in descending order.
+///
+/// [`WideBitSetLike`]: trait.WideBitSetLike.html
+/// [`BitIter`]: struct.BitIter.html
+#[derive(Debug, Clone)]
+pub struct WideBitIter<S: WideBitSetLike<L>, const L: usize = 6> {
+    pub(crate) set: S,
+    pub(crate) masks: [S::Underlying; L],
+    // The prefix of the top layer is always zero.
+    pub(crate) prefix: [u64; L],
+    // The back cursor. Every index it covers is greater than the
+    // ones covered by `masks`, and within it the lower layers hold
+    // the greater indices.
+    pub(crate) back_masks: [S::Underlying; L],
+    pub(crate) back_prefix: [u64; L],
+}
+
+impl<S: WideBitSetLike<L>, const L: usize> WideBitIter<S, L> {
+    /// Creates a new `WideBitIter`. You usually don't call this function
+    /// but just [`.iter()`] on a bit set.
+    ///
+    /// The prefix of the top layer, `prefix[L - 1]`, must be zero.
+    ///
+    /// [`.iter()`]: trait.WideBitSetLike.html#method.iter
+    pub fn new(set: S, masks: [S::Underlying; L], prefix: [u64; L]) -> Self {
+        WideBitIter {
+            set,
+            masks,
+            prefix,
+            back_masks: [S::Underlying::ZERO; L],
+            back_prefix: [0; L],
+        }
+    }
+
+    /// Creates a new `WideBitIter` over the indices of `set` that lie
+    /// within `range`. You usually don't call this function but just
+    /// [`.iter_range()`] on a bit set.
+    ///
+    /// [`.iter_range()`]: trait.WideBitSetLike.html#method.iter_range
+    pub fn new_range(set: S, range: RangeInclusive<u64>) -> Self {
+        let mut iter = WideBitIter::new(set, [S::Underlying::ZERO; L], [0; L]);
+        let bits = S::Underlying::LOG_BITS;
+        let max = wide_max_index::<S::Underlying>(L);
+        let (start, end) = (*range.start(), max.min(*range.end()));
+        if start > end {
+            return iter;
+        }
+
+        // The shifts reach 64 bits on the top layers of the widest sets.
+        let shr = |i: u64, shift: usize| i.checked_shr(shift as u32).unwrap_or(0);
+        let row = |i: u64, level: usize| (shr(i, bits * level) & ((1 << bits) - 1)) as usize;
+        let word = |i: u64, level: usize| shr(i, bits * (level + 1));
+        let bit = |row: usize| S::Underlying::ONE << S::Underlying::from_u32(row as u32);
+        let from = |row: usize| S::Underlying::MAX << S::Underlying::from_u32(row as u32);
+        let to = |row: usize| {
+            S::Underlying::MAX >> S::Underlying::from_u32((S::Underlying::BITS - 1 - row) as u32)
+        };
+
+        // Walk down the path `start` and `end` share, until they part.
+        let mut level = L - 1;
+        loop {
+            let mask = iter.set.get_from_layer(level, word(start, level) as usize);
+            let (first, last) = (row(start, level), row(end, level));
+            if level == 0 {
+                iter.masks[0] = mask & from(first) & to(last);
+                iter.prefix[0] = word(start, 0) << bits;
+                return iter;
+            }
+            if first != last {
+                // The front takes everything strictly between the two paths
+                // and the back takes what remains on the path to `end`.
+                iter.masks[level] = mask & from(first) & to(last) & !bit(first) & !bit(last);
+                if level < L - 1 {
+                    iter.prefix[level] = word(start, level) << bits;
+                }
+                let (mut start_live, mut end_live) = (
+                    mask & bit(first) != S::Underlying::ZERO,
+                    mask & bit(last) != S::Underlying::ZERO,
+                );
+                for lower in (0..level).rev() {
+                    if start_live {
+                        let mask = iter.set.get_from_layer(lower, word(start, lower) as usize);
+                        let first = row(start, lower);
+                        iter.masks[lower] = match lower {
+                            0 => mask & from(first),
+                            _ => mask & from(first) & !bit(first),
+                        };
+                        iter.prefix[lower] = word(start, lower) << bits;
+                        start_live = mask & bit(first) != S::Underlying::ZERO;
+                    }
+                    if end_live {
+                        let mask = iter.set.get_from_layer(lower, word(end, lower) as usize);
+                        let last = row(end, lower);
+                        iter.back_masks[lower] = match lower {
+                            0 => mask & to(last),
+                            _ => mask & to(last) & !bit(last),
+                        };
+                        iter.back_prefix[lower] = word(end, lower) << bits;
+                        end_live = mask & bit(last) != S::Underlying::ZERO;
+                    }
+                }
+                return iter;
+            }
+            if mask & bit(first) == S::Underlying::ZERO {
+                // Nothing is set on the shared path
+                return iter;
+            }
+            level -= 1;
+        }
+    }
+
+    /// Allows checking if set bit is contained in underlying bit set.
+    pub fn contains(&self, i: u64) -> bool {
+        self.set.contains(i)
+    }
+
+    pub(crate) fn handle_level(&mut self, level: usize) -> State<u64> {
+        use self::State::*;
+        if self.masks[level] == S::Underlying::ZERO {
+            Empty
+        } else {
+            // Take the first bit that isn't zero
+            let first_bit = self.masks[level].trailing_zeros();
+            // Remove it from the mask
+            self.masks[level] &= !(S::Underlying::ONE << S::Underlying::from_u32(first_bit));
+            // Calculate the index of it
+            let idx = self.prefix[level] | first_bit as u64;
+            if level == 0 {
+                // It's the lowest layer, so the `idx` is the next set bit
+                Value(idx)
+            } else {
+                // Take the corresponding word from the layer below
+                self.masks[level - 1] = self.set.get_from_layer(level - 1, idx as usize);
+                self.prefix[level - 1] = idx << S::Underlying::LOG_BITS;
+                Continue
+            }
+        }
+    }
+
+    pub(crate) fn handle_level_back(&mut self, level: usize) -> State<u64> {
+        use self::State::*;
+        if self.back_masks[level] == S::Underlying::ZERO {
+            Empty
+        } else {
+            // Take the last bit that isn't zero
+            let last_bit =
+                (S::Underlying::BITS - 1) as u32 - self.back_masks[level].leading_zeros();
+            // Remove it from the mask
+            self.back_masks[level] &= !(S::Underlying::ONE << S::Underlying::from_u32(last_bit));
+            // Calculate the index of it
+            let idx = self.back_prefix[level] | last_bit as u64;
+            if level == 0 {
+                // It's the lowest layer, so the `idx` is the previous set bit
+                Value(idx)
+            } else {
+                // Take the corresponding word from the layer below
+                self.back_masks[level - 1] = self.set.get_from_layer(level - 1, idx as usize);
+                self.back_prefix[level - 1] = idx << S::Underlying::LOG_BITS;
+                Continue
+            }
+        }
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> WideBitIter<&mut GenericWideBitSet<T, L>, L> {
+    /// Clears the rest of the bitset starting from the next inner layer.
+    ///
+    /// Only the layer 0 words are cleared, the storage clears the summary
+    /// bits above them.
+    pub(crate) fn clear(&mut self) {
+        use self::State::Continue;
+        while let Some(level) = (1..L).find(|&level| self.handle_level(level) == Continue) {
+            if level == 1 {
+                let idx = (self.prefix[0] >> T::LOG_BITS) as usize;
+                self.set.set_word(idx, T::ZERO);
+            }
+        }
+    }
+}
+
+impl<S: WideBitSetLike<L>, const L: usize> Iterator for WideBitIter<S, L> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use self::State::*;
+        'find: loop {
+            for level in 0..L {
+                match self.handle_level(level) {
+                    Value(v) => return Some(v),
+                    Continue => continue 'find,
+                    Empty => {}
+                }
+            }
+            // The front is exhausted, continue with the
+            // lowest bits the back cursor hasn't visited yet
+            if let Some(level) = (0..L)
+                .rev()
+                .find(|&level| self.back_masks[level] != S::Underlying::ZERO)
+            {
+                self.masks[level] = mem::replace(&mut self.back_masks[level], S::Underlying::ZERO);
+                if level < L - 1 {
+                    self.prefix[level] = self.back_prefix[level];
+                }
+                continue;
+            }
+            // There is no set bits left
+            return None;
+        }
+    }
+}
+
+impl<S: WideBitSetLike<L>, const L: usize> DoubleEndedIterator for WideBitIter<S, L> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        use self::State::*;
+        'find: loop {
+            for level in 0..L {
+                match self.handle_level_back(level) {
+                    Value(v) => return Some(v),
+                    Continue => continue 'find,
+                    Empty => {}
+                }
+            }
+            // The back is exhausted, continue with the
+            // highest bits the front hasn't visited yet
+            if let Some(level) = (0..L)
+                .rev()
+                .find(|&level| self.masks[level] != S::Underlying::ZERO)
+            {
+                self.back_masks[level] = mem::replace(&mut self.masks[level], S::Underlying::ZERO);
+                if level < L - 1 {
+                    self.back_prefix[level] = self.prefix[level];
+                }
+                continue;
+            }
+            // There is no set bits left
+            return None;
+        }
+    }
+}
+
+/// An `Iterator` over the non-zero layer 0 words of a [`WideBitSetLike`],
+/// yielding each together with its offset in layer 0.
+///
+/// Only the words below set summary bits are visited.
+///
+/// [`WideBitSetLike`]: trait.WideBitSetLike.html
+pub(crate) struct WideWordIter<S: WideBitSetLike<L>, const L: usize = 6>(WideBitIter<S, L>);
+
+impl<S: WideBitSetLike<L>, const L: usize> WideWordIter<S, L> {
+    pub(crate) fn new(set: S) -> Self {
+        WideWordIter(set.iter())
+    }
+}
+
+impl<S: WideBitSetLike<L>, const L: usize> Iterator for WideWordIter<S, L> {
+    type Item = (usize, S::Underlying);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use self::State::Continue;
+        let iter = &mut self.0;
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            if level == 1 && iter.masks[0] != S::Underlying::ZERO {
+                let word = mem::replace(&mut iter.masks[0], S::Underlying::ZERO);
+                return Some(((iter.prefix[0] >> S::Underlying::LOG_BITS) as usize, word));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use super::super::{GenericWideBitSet, WideBitSetLike};
+    use UnsignedInteger;
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_double_ended<T: UnsignedInteger>() {
+        let max = GenericWideBitSet::<T>::MAX_INDEX;
+        let indices = [0, 1, 63, 64, 65, 4095, 4096, 100_000, max / 3, max];
+        let set: GenericWideBitSet<T> = indices.iter().cloned().collect();
+
+        // meet in every possible position
+        for split in 0..=indices.len() {
+            let mut iter = (&set).iter();
+            let front = iter.by_ref().take(split).collect::<Vec<_>>();
+            let mut back = iter.rev().collect::<Vec<_>>();
+            back.reverse();
+            assert_eq!(front, &indices[..split]);
+            assert_eq!(back, &indices[split..]);
+        }
+
+        // the back cursor is drained by the front
+        let mut iter = (&set).iter();
+        assert_eq!(iter.next_back(), Some(max));
+        assert_eq!(iter.collect::<Vec<_>>(), &indices[..indices.len() - 1]);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn iterator_range<T: UnsignedInteger>() {
+        use rand::prelude::*;
+
+        let mut set = GenericWideBitSet::<T>::new();
+        let mut rng = thread_rng();
+        let limit = GenericWideBitSet::<T>::MAX_INDEX.min(1 << 34);
+        for _ in 0..10_000 {
+            set.add(rng.gen_range(0, limit));
+        }
+        set.add(0);
+        set.add(limit - 1);
+        let members = (&set).iter().collect::<Vec<_>>();
+
+        for _ in 0..100 {
+            let a = rng.gen_range(0, limit);
+            let b = rng.gen_range(a, limit);
+            let expected = members
+                .iter()
+                .cloned()
+                .filter(|&i| a <= i && i < b)
+                .collect::<Vec<_>>();
+            assert_eq!((&set).iter_range(a..b).collect::<Vec<_>>(), expected);
+            let mut rev = (&set).iter_range(a..b).rev().collect::<Vec<_>>();
+            rev.reverse();
+            assert_eq!(rev, expected);
+        }
+
+        assert_eq!((&set).iter_range(..).collect::<Vec<_>>(), members);
+        assert_eq!((&set).iter_range(0..=0).collect::<Vec<_>>(), [0]);
+        assert_eq!(
+            (&set).iter_range(limit - 1..).collect::<Vec<_>>(),
+            [limit - 1]
+        );
+        assert_eq!((&set).iter_range(5..5).count(), 0);
+        assert_eq!((&set).iter_range(u64::MAX..).count(), 0);
+    }
+}
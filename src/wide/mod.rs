@@ -0,0 +1,804 @@
+use std::iter::FromIterator;
+use std::ops::{RangeBounds, RangeInclusive};
+
+use paged::PagedStorage;
+use storage::Storage;
+use util::*;
+use {BitSetAnd, BitSetXor};
+
+pub use self::iter::WideBitIter;
+#[cfg(feature = "parallel")]
+pub use self::parallel::{WideBitParIter, WideBitProducer};
+
+use self::iter::WideWordIter;
+
+mod iter;
+mod ops;
+#[cfg(feature = "parallel")]
+mod parallel;
+
+/// A `GenericWideBitSet` is a hierarchical bit set indexed by `u64`
+/// instead of `Index`, for sets that need more than `u32::MAX` indices.
+///
+/// Its layers are stored like the ones of a [`GenericPagedBitSet`]: each
+/// layer is split in pages of `T::BITS` words, allocated once an index is
+/// added below them and freed again once they are empty, so the memory
+/// grows with the number of pages in use, not with the largest index of
+/// the set.
+///
+/// The number of layers `L` defaults to 6, which covers `T::BITS**6`
+/// indices (2^36 for `u64`). Adding beyond this limit will cause the
+/// set to panic.
+///
+/// The lookups, iterators and predicates come from [`WideBitSetLike`],
+/// and wide sets are combined lazily by [`BitSetAnd`], [`BitSetOr`],
+/// [`BitSetXor`], [`BitSetAndNot`] and [`BitSetNot`], or the matching
+/// operators.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{WideBitSet, WideBitSetLike};
+///
+/// let mut set = WideBitSet::new();
+/// set.add(3);
+/// set.add(1 << 33);
+/// assert!(set.contains(1 << 33));
+/// assert_eq!(set.last(), Some(1 << 33));
+/// assert_eq!(set.iter().collect::<Vec<u64>>(), vec![3, 1 << 33]);
+/// ```
+///
+/// [`GenericPagedBitSet`]: type.GenericPagedBitSet.html
+/// [`WideBitSetLike`]: trait.WideBitSetLike.html
+/// [`BitSetAnd`]: struct.BitSetAnd.html
+/// [`BitSetOr`]: struct.BitSetOr.html
+/// [`BitSetXor`]: struct.BitSetXor.html
+/// [`BitSetAndNot`]: struct.BitSetAndNot.html
+/// [`BitSetNot`]: struct.BitSetNot.html
+#[derive(Clone, Debug)]
+pub struct GenericWideBitSet<T: UnsignedInteger, const L: usize = 6> {
+    storage: PagedStorage<T, L>,
+    len: u64,
+}
+
+/// A `WideBitSet` is a set indexed by `u64`, made of `usize` words.
+pub type WideBitSet = GenericWideBitSet<usize>;
+
+impl<T: UnsignedInteger, const L: usize> Default for GenericWideBitSet<T, L> {
+    fn default() -> Self {
+        let () = Self::LAYERS_CHECK;
+        GenericWideBitSet {
+            storage: PagedStorage::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> GenericWideBitSet<T, L> {
+    /// The largest index the set can hold.
+    pub const MAX_INDEX: u64 = wide_max_index::<T>(L);
+
+    /// Rejects layer counts where even the top layer word covers more
+    /// than all of the `u64` values, or where the offsets of the layer 0
+    /// words don't fit in a `usize`.
+    const LAYERS_CHECK: () = assert!(
+        L >= 2 && T::LOG_BITS * (L - 1) <= usize::BITS as usize,
+        "A bit set needs at least 2 layers, and no more than needed to cover every index"
+    );
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    fn valid_range(max: u64) {
+        if max > Self::MAX_INDEX {
+            panic!(
+                "Expected index to be at most {}, found {}",
+                Self::MAX_INDEX,
+                max
+            );
+        }
+    }
+
+    /// Adds `id` to the set. Returns `true` if the value was
+    /// already in the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is larger than [`MAX_INDEX`](#associatedconstant.MAX_INDEX).
+    #[inline]
+    pub fn add(&mut self, id: u64) -> bool {
+        Self::valid_range(id);
+        let (p0, mask) = (id.offset(T::SHIFT1), id.mask::<T>(T::SHIFT0));
+        let old = self.storage.word(0, p0);
+        if old & mask != T::ZERO {
+            return true;
+        }
+        self.set_word(p0, old | mask);
+        false
+    }
+
+    /// Removes `id` from the set, returns `true` if the value
+    /// was removed, and `false` if the value was not set
+    /// to begin with.
+    ///
+    /// The pages holding `id` are freed if they don't hold any other index.
+    #[inline]
+    pub fn remove(&mut self, id: u64) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let p0 = id.offset(T::SHIFT1);
+        let word = self.storage.word(0, p0) & !id.mask::<T>(T::SHIFT0);
+        self.set_word(p0, word);
+        true
+    }
+
+    /// Removes every index of `other` from the set.
+    ///
+    /// Only the words below the summary bits both sets share are visited.
+    pub fn remove_all<B>(&mut self, other: &B)
+    where
+        B: WideBitSetLike<L, Underlying = T>,
+    {
+        use iter::State::Continue;
+        let mut iter = other.iter();
+        iter.masks[L - 1] &= self.storage.word(L - 1, 0);
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = (iter.prefix[lower] >> T::LOG_BITS) as usize;
+            if lower == 0 {
+                let word = self.storage.word(0, idx) & !other.get_from_layer(0, idx);
+                self.set_word(idx, word);
+            } else {
+                iter.masks[lower] &= self.storage.word(lower, idx);
+            }
+        }
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: u64) -> bool {
+        id <= Self::MAX_INDEX
+            && self.storage.word(0, id.offset(T::SHIFT1)) & id.mask::<T>(T::SHIFT0) != T::ZERO
+    }
+
+    /// Returns the number of indices in the set.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the set contains no indices.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of allocated layer 0 pages.
+    pub fn page_count(&self) -> usize {
+        self.storage.page_count()
+    }
+
+    /// Returns the number of bytes allocated on the heap by the set.
+    pub fn heap_size(&self) -> usize {
+        self.storage.heap_size()
+    }
+
+    /// Completely wipes out the bit set, freeing every page.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.len = 0;
+    }
+
+    /// Adds every index within `range` to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range ends past [`MAX_INDEX`](#associatedconstant.MAX_INDEX).
+    /// An unbounded end stands for `MAX_INDEX`.
+    pub fn insert_range<R: RangeBounds<u64>>(&mut self, range: R) {
+        let range = inclusive_wide_range(range, Self::MAX_INDEX);
+        if range.is_empty() {
+            return;
+        }
+        Self::valid_range(*range.end());
+        self.update_range(range, |word, mask| word | mask);
+    }
+
+    /// Removes every index within `range` from the set.
+    pub fn remove_range<R: RangeBounds<u64>>(&mut self, range: R) {
+        let range = inclusive_wide_range(range, Self::MAX_INDEX);
+        let last = match WideBitSetLike::last(self) {
+            Some(last) => last,
+            None => return,
+        };
+        let end = (*range.end()).min(last);
+        if *range.start() > end {
+            return;
+        }
+        self.update_range(*range.start()..=end, |word, mask| word & !mask);
+    }
+
+    /// Applies `op` to every layer 0 word overlapping `range` with a mask of
+    /// the bits within the range.
+    fn update_range<F>(&mut self, range: RangeInclusive<u64>, op: F)
+    where
+        F: Fn(T, T) -> T,
+    {
+        let (start, end) = (*range.start(), *range.end());
+        let (first, last) = (start.offset(T::SHIFT1), end.offset(T::SHIFT1));
+        for p0 in first..=last {
+            let mut mask = T::MAX;
+            if p0 == first {
+                mask &= T::MAX << start.row(T::SHIFT0);
+            }
+            if p0 == last {
+                mask &= T::MAX >> (T::from_u32(T::BITS as u32 - 1) - end.row(T::SHIFT0));
+            }
+
+            let word = op(self.storage.word(0, p0), mask);
+            self.set_word(p0, word);
+        }
+    }
+
+    /// Replaces the layer 0 word at `p0` with `word`, keeping the length,
+    /// the summary layers and the pages up to date.
+    #[inline]
+    pub(crate) fn set_word(&mut self, p0: usize, word: T) {
+        let old = self.storage.set_word(p0, word);
+        self.len = self.len + word.count_ones() as u64 - old.count_ones() as u64;
+    }
+}
+
+/// The `u64` indexed counterpart of [`BitSetLike`], implemented by
+/// [`GenericWideBitSet`] and by the combinators of wide sets.
+///
+/// It works like `BitSetLike`, except that the indices are `u64`, so the
+/// offsets of the layer 0 words may exceed `u32::MAX`.
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`GenericWideBitSet`]: struct.GenericWideBitSet.html
+pub trait WideBitSetLike<const L: usize = 6> {
+    /// Type of the underlying bit storage
+    type Underlying: UnsignedInteger;
+
+    /// Gets the word corresponding to layer and index.
+    ///
+    /// The `layer` should be in the range [0, L - 1]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying;
+
+    /// Gets the word of the full block summary corresponding to layer and
+    /// index, see [`BitSetLike::get_full_from_layer`].
+    ///
+    /// [`BitSetLike::get_full_from_layer`]: trait.BitSetLike.html#method.get_full_from_layer
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        if layer == 0 {
+            self.get_from_layer(0, idx)
+        } else {
+            Self::Underlying::ZERO
+        }
+    }
+
+    /// Returns true if this `WideBitSetLike` contains nothing, and false otherwise.
+    fn is_empty(&self) -> bool {
+        self.get_from_layer(L - 1, 0) == Self::Underlying::ZERO
+    }
+
+    /// Returns the number of indices in the set.
+    ///
+    /// The default implementation only visits the layer 0 words whose
+    /// summary bits are set and counts their ones.
+    fn count(&self) -> u64 {
+        use iter::State::Continue;
+        let mut count = 0;
+        let mut iter = self.iter();
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            if level == 1 {
+                count += iter.masks[0].count_ones() as u64;
+            }
+        }
+        count
+    }
+
+    /// Returns `true` if every index in this set is also in `other`.
+    ///
+    /// The words of both sets are compared layer by layer, from the top, as
+    /// in [`BitSetLike::is_subset`].
+    ///
+    /// [`BitSetLike::is_subset`]: trait.BitSetLike.html#method.is_subset
+    fn is_subset<S>(&self, other: &S) -> bool
+    where
+        S: WideBitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        use iter::State::Continue;
+        let zero = Self::Underlying::ZERO;
+        // Summary bits may be stale, so only full blocks, and layer 0 words,
+        // tell for sure that this set holds indices missing from `other`.
+        let check = |mask: &mut Self::Underlying, level: usize, idx: usize| {
+            let full = match level {
+                0 => *mask,
+                _ => self.get_full_from_layer(level, idx),
+            };
+            if full & !other.get_from_layer(level, idx) != zero {
+                return false;
+            }
+            *mask &= !other.get_full_from_layer(level, idx);
+            true
+        };
+        let mut iter = self.iter();
+        if !check(&mut iter.masks[L - 1], L - 1, 0) {
+            return false;
+        }
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = (iter.prefix[lower] >> Self::Underlying::LOG_BITS) as usize;
+            if !check(&mut iter.masks[lower], lower, idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns `true` if every index in `other` is also in this set.
+    fn is_superset<S>(&self, other: &S) -> bool
+    where
+        S: WideBitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if this set has no index in common with `other`.
+    fn is_disjoint<S>(&self, other: &S) -> bool
+    where
+        S: WideBitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        BitSetAnd::<_, _, L>(self, other).first().is_none()
+    }
+
+    /// Returns `true` if this set and `other` hold the same indices, whatever
+    /// their types.
+    fn set_eq<S>(&self, other: &S) -> bool
+    where
+        S: WideBitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        BitSetXor::<_, _, L>(self, other).first().is_none()
+    }
+
+    /// Returns `true` if this set has at least one index in common with `other`.
+    fn intersects<S>(&self, other: &S) -> bool
+    where
+        S: WideBitSetLike<L, Underlying = Self::Underlying> + ?Sized,
+    {
+        !self.is_disjoint(other)
+    }
+
+    /// Returns the smallest index in the set, or `None` if the set is empty.
+    fn first(&self) -> Option<u64> {
+        self.next_set(0)
+    }
+
+    /// Returns the largest index in the set, or `None` if the set is empty.
+    fn last(&self) -> Option<u64> {
+        self.prev_set(u64::MAX)
+    }
+
+    /// Returns the smallest index in the set that is greater than or equal to `from`.
+    ///
+    /// Empty regions are skipped using the summary layers, so this doesn't
+    /// scan layer 0 word by word.
+    fn next_set(&self, from: u64) -> Option<u64> {
+        let bits = Self::Underlying::LOG_BITS;
+        let row = (1 << bits) - 1;
+        if from > wide_max_index::<Self::Underlying>(L) {
+            return None;
+        }
+        let (mut level, mut pos) = (0, from);
+        loop {
+            if level == L || (level == L - 1 && pos >> bits != 0) {
+                // past the end of the keyspace
+                return None;
+            }
+            let word = self.get_from_layer(level, (pos >> bits) as usize)
+                & (Self::Underlying::MAX << Self::Underlying::from_u64(pos & row));
+            if word == Self::Underlying::ZERO {
+                // nothing left in this word, continue with the next one a layer up
+                pos = (pos >> bits) + 1;
+                level += 1;
+                continue;
+            }
+            pos = (pos & !row) | word.trailing_zeros() as u64;
+            if level == 0 {
+                return Some(pos);
+            }
+            level -= 1;
+            pos <<= bits;
+        }
+    }
+
+    /// Returns the largest index in the set that is less than or equal to `from`.
+    ///
+    /// Empty regions are skipped using the summary layers, so this doesn't
+    /// scan layer 0 word by word.
+    fn prev_set(&self, from: u64) -> Option<u64> {
+        let bits = Self::Underlying::LOG_BITS;
+        let row = (1 << bits) - 1;
+        let last = wide_max_index::<Self::Underlying>(L);
+        let (mut level, mut pos) = (0, from.min(last));
+        loop {
+            let word = self.get_from_layer(level, (pos >> bits) as usize)
+                & (Self::Underlying::MAX >> Self::Underlying::from_u64(row - (pos & row)));
+            if word == Self::Underlying::ZERO {
+                if pos >> bits == 0 {
+                    // nothing before this word
+                    return None;
+                }
+                // continue with the previous word a layer up
+                pos = (pos >> bits) - 1;
+                level += 1;
+                continue;
+            }
+            pos = (pos & !row) | (row - word.leading_zeros() as u64);
+            if level == 0 {
+                return Some(pos);
+            }
+            level -= 1;
+            pos = (pos << bits) | row;
+        }
+    }
+
+    /// Allows checking if set bit is contained in the bit set.
+    fn contains(&self, i: u64) -> bool;
+
+    /// Create an iterator that will scan over the keyspace
+    fn iter(self) -> WideBitIter<Self, L>
+    where
+        Self: Sized,
+    {
+        let mut masks = [Self::Underlying::ZERO; L];
+        masks[L - 1] = self.get_from_layer(L - 1, 0);
+
+        WideBitIter::new(self, masks, [0; L])
+    }
+
+    /// Create an iterator that will scan over the part of the keyspace within `range`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{WideBitSet, WideBitSetLike};
+    ///
+    /// let set: WideBitSet = (0..10).map(|i| i << 32).collect();
+    /// let range = (&set).iter_range(1 << 33..=3 << 32).collect::<Vec<_>>();
+    /// assert_eq!(range, vec![2 << 32, 3 << 32]);
+    /// ```
+    fn iter_range<R>(self, range: R) -> WideBitIter<Self, L>
+    where
+        Self: Sized,
+        R: RangeBounds<u64>,
+    {
+        WideBitIter::new_range(self, inclusive_wide_range(range, u64::MAX))
+    }
+
+    /// Create a parallel iterator that will scan over the keyspace
+    #[cfg(feature = "parallel")]
+    fn par_iter(self) -> WideBitParIter<Self, L>
+    where
+        Self: Sized,
+    {
+        WideBitParIter::new(self)
+    }
+
+    /// Create a parallel iterator that will scan over the part of the keyspace within `range`
+    #[cfg(feature = "parallel")]
+    fn par_iter_range<R>(self, range: R) -> WideBitParIter<Self, L>
+    where
+        Self: Sized,
+        R: RangeBounds<u64>,
+    {
+        WideBitParIter::new(self).range(range)
+    }
+}
+
+impl<T, const L: usize> WideBitSetLike<L> for &T
+where
+    T: WideBitSetLike<L> + ?Sized,
+{
+    type Underlying = T::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (*self).get_from_layer(layer, idx)
+    }
+
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (*self).get_full_from_layer(layer, idx)
+    }
+
+    #[inline]
+    fn count(&self) -> u64 {
+        (*self).count()
+    }
+
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        (*self).contains(i)
+    }
+}
+
+impl<T, const L: usize> WideBitSetLike<L> for &mut T
+where
+    T: WideBitSetLike<L> + ?Sized,
+{
+    type Underlying = T::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (**self).get_from_layer(layer, idx)
+    }
+
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T::Underlying {
+        (**self).get_full_from_layer(layer, idx)
+    }
+
+    #[inline]
+    fn count(&self) -> u64 {
+        (**self).count()
+    }
+
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        (**self).contains(i)
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> WideBitSetLike<L> for GenericWideBitSet<T, L> {
+    type Underlying = T;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> T {
+        self.storage.word(layer, idx)
+    }
+
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> T {
+        match layer {
+            0 => self.storage.word(0, idx),
+            _ => self.storage.full(layer, idx),
+        }
+    }
+
+    #[inline]
+    fn count(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        self.contains(i)
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> PartialEq for GenericWideBitSet<T, L> {
+    /// Compares the indices of both sets, whatever pages they allocated.
+    #[inline]
+    fn eq(&self, rhv: &GenericWideBitSet<T, L>) -> bool {
+        self.len == rhv.len && WideWordIter::new(self).eq(WideWordIter::new(rhv))
+    }
+}
+impl<T: UnsignedInteger, const L: usize> Eq for GenericWideBitSet<T, L> {}
+
+impl<T: UnsignedInteger, const L: usize> FromIterator<u64> for GenericWideBitSet<T, L> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut bitset = Self::new();
+        Extend::extend(&mut bitset, iter);
+        bitset
+    }
+}
+
+impl<T: UnsignedInteger, const L: usize> Extend<u64> for GenericWideBitSet<T, L> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        for item in iter {
+            self.add(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use std::mem::size_of;
+
+    use super::{GenericWideBitSet, WideBitSetLike};
+    use util::{Row, UnsignedInteger};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn add_remove<T: UnsignedInteger>() {
+        let mut set = GenericWideBitSet::<T>::new();
+        let ids = [0, 63, 64, 100_000, 5_000_000];
+        for &id in &ids {
+            assert!(!set.add(id));
+            assert!(set.add(id));
+        }
+        assert_eq!(set.len(), 5);
+        assert!(!set.contains(1));
+        assert!(!set.contains(u64::MAX));
+        assert_eq!((&set).iter().collect::<Vec<_>>(), ids);
+
+        assert!(set.remove(64));
+        assert!(!set.remove(64));
+        assert!(!set.remove(1 << 40));
+        assert_eq!(set.len(), 4);
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            [0, 63, 100_000, 5_000_000]
+        );
+
+        for &id in &ids {
+            set.remove(id);
+        }
+        assert!(set.is_empty());
+        assert_eq!((&set).iter().next(), None);
+        assert_eq!(set.page_count(), 0);
+        assert_eq!(set.heap_size(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn collect<T: UnsignedInteger>() {
+        let set: GenericWideBitSet<T, 3> = (0..10_000).filter(|i| i % 3 == 0).collect();
+        assert_eq!(set.len(), 3_334);
+        assert!((&set).iter().eq((0..10_000).filter(|i| i % 3 == 0)));
+        assert!((&set)
+            .iter()
+            .rev()
+            .eq((0..10_000).filter(|i| i % 3 == 0).rev()));
+        set.clone().clear();
+        assert_eq!(set, (&set).into_iter().collect());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn sparse<T: UnsignedInteger>() {
+        let mut set = GenericWideBitSet::<T>::new();
+        let high = GenericWideBitSet::<T>::MAX_INDEX.min(1 << 33);
+        set.add(high);
+        set.add(7);
+        assert_eq!(set.page_count(), 2);
+        assert_eq!(set.first(), Some(7));
+        assert_eq!(set.last(), Some(high));
+        assert_eq!(set.next_set(8), Some(high));
+        assert_eq!(set.next_set(high + 1), None);
+        assert_eq!(set.prev_set(high - 1), Some(7));
+        assert_eq!(set.prev_set(6), None);
+        assert_eq!((&set).iter().rev().collect::<Vec<_>>(), [high, 7]);
+
+        set.remove(high);
+        assert_eq!(set.page_count(), 1);
+        assert_eq!(set.last(), Some(7));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn one_huge_index<T: UnsignedInteger>() {
+        let mut set = GenericWideBitSet::<T>::new();
+        let max = GenericWideBitSet::<T>::MAX_INDEX;
+        set.add(max);
+        // A single page per layer, whatever the index.
+        let page = T::BITS * size_of::<T>();
+        assert_eq!(set.page_count(), 1);
+        assert!(set.heap_size() <= 2 * 6 * (page + size_of::<(usize, Box<[T]>)>()));
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [max]);
+        assert_eq!(set.first(), Some(max));
+
+        set.remove(max);
+        assert_eq!(set.heap_size(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn ranges<T: UnsignedInteger>() {
+        let bits = T::BITS as u64;
+        let mut set = GenericWideBitSet::<T>::new();
+        set.insert_range(3..bits * bits + 5);
+        assert_eq!(set.len(), bits * bits + 2);
+        assert!((&set).iter().eq(3..bits * bits + 5));
+        set.remove_range(bits - 1..=bits);
+        assert_eq!(set.len(), bits * bits);
+        assert!(!set.contains(bits));
+        assert_eq!(set.next_set(bits - 1), Some(bits + 1));
+        assert!((&set)
+            .iter_range(bits - 2..bits + 3)
+            .eq(vec![bits - 2, bits + 1, bits + 2]));
+        assert!((&set).iter_range(..10).rev().eq((3..10).rev()));
+
+        set.remove_range(..);
+        assert!(set.is_empty());
+        assert_eq!(set.page_count(), 0);
+        assert_eq!(set, GenericWideBitSet::new());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn subset<T: UnsignedInteger>() {
+        let a: GenericWideBitSet<T> = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let b: GenericWideBitSet<T> = (0..20_000).filter(|i| i % 3 == 0).collect();
+        let small: GenericWideBitSet<T> = [4, 1_000, 9_998].iter().cloned().collect();
+
+        assert!(small.is_subset(&a));
+        assert!(a.is_superset(&small));
+        assert!(!a.is_subset(&small));
+        assert!(!small.is_subset(&b));
+        assert!(a.intersects(&b));
+        assert!(!small.is_disjoint(&a));
+        assert!(GenericWideBitSet::<T>::new().is_subset(&a));
+        let high: GenericWideBitSet<T> = (1 << 29..(1 << 29) + 100).collect();
+        assert!(high.is_disjoint(&a));
+        assert!(high.set_eq(&high.clone()));
+        assert!(!high.set_eq(&a));
+    }
+
+    #[test]
+    fn eq_ignores_allocation() {
+        let mut grown = GenericWideBitSet::<u64>::new();
+        grown.add(5);
+        grown.add(6);
+        grown.remove(6);
+        let mut other = GenericWideBitSet::<u64>::new();
+        other.add(1 << 25);
+        other.add(5);
+        other.remove(1 << 25);
+        assert_eq!(grown, other);
+        assert_eq!(other, [5].iter().cloned().collect());
+    }
+
+    #[test]
+    fn max_index() {
+        assert_eq!(GenericWideBitSet::<u32, 7>::MAX_INDEX, (1 << 35) - 1);
+        assert_eq!(GenericWideBitSet::<u64, 6>::MAX_INDEX, (1 << 36) - 1);
+        assert_eq!(GenericWideBitSet::<u64, 11>::MAX_INDEX, u64::MAX);
+
+        let mut set = GenericWideBitSet::<u32, 3>::new();
+        set.insert_range(32_766..);
+        set.add(0);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [0, 32_766, 32_767]);
+        assert_eq!((&set).iter().rev().collect::<Vec<_>>(), [32_767, 32_766, 0]);
+        assert_eq!(set.next_set(1), Some(32_766));
+        assert_eq!(set.next_set(32_768), None);
+        assert_eq!(set.prev_set(u64::MAX), Some(32_767));
+        assert_eq!(set.prev_set(32_765), Some(0));
+
+        let mut set = GenericWideBitSet::<u64, 11>::new();
+        set.add(u64::MAX);
+        set.add(1);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [1, u64::MAX]);
+        assert_eq!((&set).iter_range(2..).collect::<Vec<_>>(), [u64::MAX]);
+        assert_eq!((&set).iter().rev().collect::<Vec<_>>(), [u64::MAX, 1]);
+        assert_eq!(set.last(), Some(u64::MAX));
+        assert_eq!(set.next_set(2), Some(u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected index to be at most 32767, found 32768")]
+    fn out_of_range() {
+        GenericWideBitSet::<u32, 3>::new().add(1 << 15);
+    }
+
+    #[test]
+    fn row_above_u32() {
+        let id: u64 = (5 << 36) | (7 << 30) | 3;
+        assert_eq!(id.row::<u64>(0), 3);
+        assert_eq!(id.row::<u64>(30), 7);
+        assert_eq!(id.row::<u64>(36), 5);
+        assert_eq!(id.offset(36), 5);
+        assert_eq!(id.offset(64), 0);
+        assert_eq!(id.mask::<u32>(30), 1 << 7);
+    }
+}
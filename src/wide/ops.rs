@@ -0,0 +1,372 @@
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub, SubAssign,
+};
+
+use util::*;
+use {BitSetAnd, BitSetAndNot, BitSetNot, BitSetOr, BitSetXor};
+
+use super::{GenericWideBitSet, WideBitIter, WideBitSetLike};
+
+impl<B, T, const L: usize> BitOrAssign<&B> for GenericWideBitSet<T, L>
+where
+    T: UnsignedInteger,
+    B: WideBitSetLike<L, Underlying = T>,
+{
+    fn bitor_assign(&mut self, lhs: &B) {
+        use iter::State::Continue;
+        let mut iter = lhs.iter();
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = (iter.prefix[lower] >> T::LOG_BITS) as usize;
+            if lower == 0 {
+                let word = self.get_from_layer(0, idx) | lhs.get_from_layer(0, idx);
+                self.set_word(idx, word);
+            }
+        }
+    }
+}
+
+impl<B, T, const L: usize> BitAndAssign<&B> for GenericWideBitSet<T, L>
+where
+    T: UnsignedInteger,
+    B: WideBitSetLike<L, Underlying = T>,
+{
+    fn bitand_assign(&mut self, lhs: &B) {
+        use iter::State::*;
+        let mut iter = lhs.iter();
+        iter.masks[L - 1] &= self.get_from_layer(L - 1, 0);
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = (iter.prefix[lower] >> T::LOG_BITS) as usize;
+            let our_layer = self.get_from_layer(lower, idx);
+            let their_layer = lhs.get_from_layer(lower, idx);
+
+            iter.masks[lower] &= our_layer;
+
+            let mut masks = [T::ZERO; L];
+            masks[lower] = our_layer & !their_layer;
+            WideBitIter::new(&mut *self, masks, iter.prefix).clear();
+
+            if lower == 0 {
+                self.set_word(idx, our_layer & their_layer);
+            }
+        }
+        let (ours, theirs) = (self.get_from_layer(L - 1, 0), lhs.get_from_layer(L - 1, 0));
+        let mut masks = [T::ZERO; L];
+        masks[L - 1] = ours & !theirs;
+        WideBitIter::new(&mut *self, masks, [0; L]).clear();
+    }
+}
+
+impl<B, T, const L: usize> BitXorAssign<&B> for GenericWideBitSet<T, L>
+where
+    T: UnsignedInteger,
+    B: WideBitSetLike<L, Underlying = T>,
+{
+    fn bitxor_assign(&mut self, lhs: &B) {
+        use iter::State::*;
+        let mut iter = lhs.iter();
+        while let Some(level) = (1..L).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = (iter.prefix[lower] >> T::LOG_BITS) as usize;
+
+            if lower == 0 {
+                let word = self.get_from_layer(0, idx) ^ lhs.get_from_layer(0, idx);
+                self.set_word(idx, word);
+            }
+        }
+    }
+}
+
+impl<B, T, const L: usize> SubAssign<&B> for GenericWideBitSet<T, L>
+where
+    T: UnsignedInteger,
+    B: WideBitSetLike<L, Underlying = T>,
+{
+    fn sub_assign(&mut self, lhs: &B) {
+        self.remove_all(lhs);
+    }
+}
+
+impl<A, B, const L: usize> WideBitSetLike<L> for BitSetAnd<A, B, L>
+where
+    A: WideBitSetLike<L>,
+    B: WideBitSetLike<L, Underlying = A::Underlying>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) & self.1.get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) & self.1.get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        self.0.contains(i) && self.1.contains(i)
+    }
+}
+
+impl<A, B, const L: usize> WideBitSetLike<L> for BitSetOr<A, B, L>
+where
+    A: WideBitSetLike<L>,
+    B: WideBitSetLike<L, Underlying = A::Underlying>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) | self.1.get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) | self.1.get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        self.0.contains(i) || self.1.contains(i)
+    }
+}
+
+impl<A, B, const L: usize> WideBitSetLike<L> for BitSetAndNot<A, B, L>
+where
+    A: WideBitSetLike<L>,
+    B: WideBitSetLike<L, Underlying = A::Underlying>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_from_layer(layer, idx) & !self.1.get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        self.0.get_full_from_layer(layer, idx) & !self.1.get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        self.0.contains(i) && !self.1.contains(i)
+    }
+}
+
+impl<A, const L: usize> WideBitSetLike<L> for BitSetNot<A, L>
+where
+    A: WideBitSetLike<L>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> A::Underlying {
+        let word = if layer == 0 {
+            !self.0.get_from_layer(0, idx)
+        } else {
+            !self.0.get_full_from_layer(layer, idx)
+        };
+        if layer == L - 1 {
+            word & wide_top_mask(L)
+        } else {
+            word
+        }
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> A::Underlying {
+        let word = !self.0.get_from_layer(layer, idx);
+        if layer == L - 1 {
+            word & wide_top_mask(L)
+        } else {
+            word
+        }
+    }
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        !self.0.contains(i)
+    }
+}
+
+impl<A, B, const L: usize> WideBitSetLike<L> for BitSetXor<A, B, L>
+where
+    A: WideBitSetLike<L>,
+    B: WideBitSetLike<L, Underlying = A::Underlying>,
+{
+    type Underlying = A::Underlying;
+
+    #[inline]
+    fn get_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let xor = BitSetAnd::<_, _, L>(
+            BitSetOr::<_, _, L>(&self.0, &self.1),
+            BitSetNot::<_, L>(BitSetAnd::<_, _, L>(&self.0, &self.1)),
+        );
+        xor.get_from_layer(layer, idx)
+    }
+    #[inline]
+    fn get_full_from_layer(&self, layer: usize, idx: usize) -> Self::Underlying {
+        let xor = BitSetAnd::<_, _, L>(
+            BitSetOr::<_, _, L>(&self.0, &self.1),
+            BitSetNot::<_, L>(BitSetAnd::<_, _, L>(&self.0, &self.1)),
+        );
+        xor.get_full_from_layer(layer, idx)
+    }
+    #[inline]
+    fn contains(&self, i: u64) -> bool {
+        self.0.contains(i) != self.1.contains(i)
+    }
+}
+
+// The operators of the combinators are already implemented for `BitSetLike`
+// operands, so nested wide combinations are built with the constructors.
+macro_rules! wide_operator {
+    ( impl < ( $( $lifetime:tt )* ) > for $bitset:ty ) => {
+        impl<$( $lifetime, )* T, const L: usize> IntoIterator for $bitset
+            where
+                T: UnsignedInteger,
+        {
+            type Item = u64;
+            type IntoIter = WideBitIter<Self, L>;
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<$( $lifetime, )* T, const L: usize> Not for $bitset
+            where
+                T: UnsignedInteger,
+        {
+            type Output = BitSetNot<Self, L>;
+            fn not(self) -> Self::Output {
+                BitSetNot(self)
+            }
+        }
+
+        impl<$( $lifetime, )* T, OtherBitSetLike, const L: usize> BitAnd<OtherBitSetLike> for $bitset
+            where
+                T: UnsignedInteger,
+                OtherBitSetLike: WideBitSetLike<L, Underlying = T>,
+        {
+            type Output = BitSetAnd<Self, OtherBitSetLike, L>;
+            fn bitand(self, rhs: OtherBitSetLike) -> Self::Output {
+                BitSetAnd(self, rhs)
+            }
+        }
+
+        impl<$( $lifetime, )* T, OtherBitSetLike, const L: usize> BitOr<OtherBitSetLike> for $bitset
+            where
+                T: UnsignedInteger,
+                OtherBitSetLike: WideBitSetLike<L, Underlying = T>,
+        {
+            type Output = BitSetOr<Self, OtherBitSetLike, L>;
+            fn bitor(self, rhs: OtherBitSetLike) -> Self::Output {
+                BitSetOr(self, rhs)
+            }
+        }
+
+        impl<$( $lifetime, )* T, OtherBitSetLike, const L: usize> BitXor<OtherBitSetLike> for $bitset
+            where
+                T: UnsignedInteger,
+                OtherBitSetLike: WideBitSetLike<L, Underlying = T>,
+        {
+            type Output = BitSetXor<Self, OtherBitSetLike, L>;
+            fn bitxor(self, rhs: OtherBitSetLike) -> Self::Output {
+                BitSetXor(self, rhs)
+            }
+        }
+
+        impl<$( $lifetime, )* T, OtherBitSetLike, const L: usize> Sub<OtherBitSetLike> for $bitset
+            where
+                T: UnsignedInteger,
+                OtherBitSetLike: WideBitSetLike<L, Underlying = T>,
+        {
+            type Output = BitSetAndNot<Self, OtherBitSetLike, L>;
+            fn sub(self, rhs: OtherBitSetLike) -> Self::Output {
+                BitSetAndNot(self, rhs)
+            }
+        }
+    }
+}
+
+wide_operator!(impl<()> for GenericWideBitSet<T, L>);
+wide_operator!(impl<('a)> for &'a GenericWideBitSet<T, L>);
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+
+    use super::super::{GenericWideBitSet, WideBitSetLike};
+    use {BitSetAnd, BitSetNot, BitSetOr, UnsignedInteger};
+
+    fn expected<T: UnsignedInteger>(f: fn(bool, bool) -> bool) -> GenericWideBitSet<T> {
+        (0..20_000)
+            .filter(|i| f(i % 2 == 0 && *i < 10_000, i % 3 == 0))
+            .collect()
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn assign<T: UnsignedInteger>() {
+        let a: GenericWideBitSet<T> = expected(|a, _| a);
+        let b: GenericWideBitSet<T> = expected(|_, b| b);
+
+        let mut and = a.clone();
+        and &= &b;
+        assert_eq!(and, expected(|a, b| a && b));
+        let mut or = a.clone();
+        or |= &b;
+        assert_eq!(or, expected(|a, b| a || b));
+        let mut xor = a.clone();
+        xor ^= &b;
+        assert_eq!(xor, expected(|a, b| a != b));
+        let mut sub = a.clone();
+        sub -= &b;
+        assert_eq!(sub, expected(|a, b| a && !b));
+        assert_eq!(sub.len(), (&sub).iter().count() as u64);
+
+        let mut empty = a.clone();
+        empty -= &a;
+        assert!(empty.is_empty());
+        assert_eq!(empty.page_count(), 0);
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn combinators<T: UnsignedInteger>() {
+        let a: GenericWideBitSet<T> = expected(|a, _| a);
+        let b: GenericWideBitSet<T> = expected(|_, b| b);
+
+        assert!((&a & &b).iter().eq(&expected::<T>(|a, b| a && b)));
+        assert!((&a | &b).iter().eq(&expected::<T>(|a, b| a || b)));
+        assert!((&a ^ &b).iter().eq(&expected::<T>(|a, b| a != b)));
+        assert!((&a - &b).iter().eq(&expected::<T>(|a, b| a && !b)));
+        assert_eq!((&a & &b).count(), expected::<T>(|a, b| a && b).len());
+        assert!((&a & &b).contains(6) && !(&a & &b).contains(4));
+        assert!((&a - &b).is_subset(&a));
+        assert!((&a - &b).is_disjoint(&b));
+
+        let nested = BitSetOr::<_, _, 6>(BitSetAnd::<_, _, 6>(&a, &b), BitSetNot::<_, 6>(&a));
+        assert!(nested
+            .iter_range(..20)
+            .eq(vec![0, 1, 3, 5, 6, 7, 9, 11, 12, 13, 15, 17, 18, 19]));
+        assert_eq!(
+            (!&a).iter_range(9_998..10_003).collect::<Vec<_>>(),
+            [9_999, 10_000, 10_001, 10_002]
+        );
+
+        let mut set = a.clone();
+        set |= &(&b - &a);
+        assert_eq!(set, expected(|a, b| a || b));
+        set &= &!&b;
+        assert_eq!(set, expected(|a, b| a && !b));
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn not_covers_max_index<T: UnsignedInteger>() {
+        let max = GenericWideBitSet::<T, 3>::MAX_INDEX;
+        let mut set = GenericWideBitSet::<T, 3>::new();
+        set.insert_range(..max - 2);
+        assert_eq!((!&set).iter().collect::<Vec<_>>(), [max - 2, max - 1, max]);
+        assert_eq!((!&set).last(), Some(max));
+
+        let set = GenericWideBitSet::<u64, 11>::new();
+        assert_eq!((!&set).last(), Some(u64::MAX));
+        assert_eq!((!&set).iter().rev().nth(3), Some(u64::MAX - 3));
+    }
+}
@@ -0,0 +1,254 @@
+use std::mem;
+use std::ops::{RangeBounds, RangeInclusive};
+
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::ParallelIterator;
+
+use util::{average_ones, inclusive_wide_range};
+use UnsignedInteger;
+
+use super::{WideBitIter, WideBitSetLike};
+
+/// A `ParallelIterator` over a [`WideBitSetLike`] structure, the `u64`
+/// indexed counterpart of [`BitParIter`].
+///
+/// [`WideBitSetLike`]: trait.WideBitSetLike.html
+/// [`BitParIter`]: struct.BitParIter.html
+#[derive(Debug)]
+pub struct WideBitParIter<S, const L: usize = 6>(S, u8, Option<RangeInclusive<u64>>);
+
+impl<S, const L: usize> WideBitParIter<S, L> {
+    /// Creates a new `WideBitParIter`. You usually don't call this function
+    /// but just [`.par_iter()`] on a bit set.
+    ///
+    /// Default layer split amount is 3.
+    ///
+    /// [`.par_iter()`]: trait.WideBitSetLike.html#method.par_iter
+    pub fn new(set: S) -> Self {
+        WideBitParIter(set, 3, None)
+    }
+
+    /// Restricts the iteration to the indices within `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rayon;
+    /// # extern crate hibitset;
+    /// # use hibitset::{WideBitSet, WideBitSetLike};
+    /// # use rayon::iter::ParallelIterator;
+    /// # fn main() {
+    /// let bitset: WideBitSet = (0..100_000).map(|i| i << 16).collect();
+    /// let count = bitset.par_iter()
+    ///     .range(10_000 << 16..20_000 << 16)
+    ///     .count();
+    /// assert_eq!(count, 10_000);
+    /// # }
+    /// ```
+    pub fn range<R: RangeBounds<u64>>(mut self, range: R) -> Self {
+        self.2 = Some(inclusive_wide_range(range, u64::MAX));
+        self
+    }
+
+    /// Sets how many layers are split when forking, see
+    /// [`BitParIter::layers_split`].
+    ///
+    /// The value should be in range [1, L - 1], where L is the number of layers.
+    ///
+    /// [`BitParIter::layers_split`]: struct.BitParIter.html#method.layers_split
+    pub fn layers_split(mut self, layers: u8) -> Self {
+        assert!(layers >= 1);
+        assert!((layers as usize) < L);
+        self.1 = layers;
+        self
+    }
+}
+
+impl<S, const L: usize> ParallelIterator for WideBitParIter<S, L>
+where
+    S: WideBitSetLike<L> + Send + Sync,
+    <S as WideBitSetLike<L>>::Underlying: Send + Sync,
+{
+    type Item = u64;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let iter = match self.2 {
+            Some(range) => (&self.0).iter_range(range),
+            None => (&self.0).iter(),
+        };
+        bridge_unindexed(WideBitProducer(iter, self.1), consumer)
+    }
+}
+
+/// Allows splitting and internally iterating through a wide bit set.
+///
+/// Usually used internally by `WideBitParIter`, it splits like
+/// [`BitProducer`].
+///
+/// [`BitProducer`]: struct.BitProducer.html
+#[derive(Debug)]
+pub struct WideBitProducer<'a, S: 'a + WideBitSetLike<L>, const L: usize = 6>(
+    pub WideBitIter<&'a S, L>,
+    pub u8,
+);
+
+impl<'a, S: 'a + Send + Sync, const L: usize> UnindexedProducer for WideBitProducer<'a, S, L>
+where
+    S: WideBitSetLike<L>,
+    <S as WideBitSetLike<L>>::Underlying: Send + Sync,
+{
+    type Item = u64;
+
+    /// Splits the back and the layers of the front off first, then the
+    /// highest layer with several set bits in halves of its set bits, as
+    /// `BitProducer` does.
+    fn split(mut self) -> (Self, Option<Self>) {
+        let splits = self.1;
+        if let Some(other) = self.split_off() {
+            return (self, Some(other));
+        }
+        let other = {
+            let mut handle_level = |level: usize| {
+                if self.0.masks[level] == S::Underlying::ZERO {
+                    // Skip the empty layers
+                    None
+                } else {
+                    // Top levels prefix is zero because there is nothing before it
+                    let level_prefix = self.0.prefix[level];
+                    let first_bit = self.0.masks[level].trailing_zeros();
+                    average_ones(self.0.masks[level])
+                        .map(|average_bit| {
+                            let mask = (S::Underlying::ONE << average_bit) - S::Underlying::ONE;
+                            let mut other = WideBitProducer(
+                                WideBitIter::new(self.0.set, [S::Underlying::ZERO; L], [0; L]),
+                                splits,
+                            );
+                            // The `other` is the more significant half of the mask
+                            other.0.masks[level] = self.0.masks[level] & !mask;
+                            other.0.prefix[level - 1] =
+                                (level_prefix | average_bit.to_u64()) << S::Underlying::LOG_BITS;
+                            // The upper portion of the prefix is maintained, because the `other`
+                            // will iterate the same subtree as the `self` does
+                            other.0.prefix[level..].copy_from_slice(&self.0.prefix[level..]);
+                            // And the `self` is the less significant one
+                            self.0.masks[level] &= mask;
+                            self.0.prefix[level - 1] =
+                                (level_prefix | first_bit as u64) << S::Underlying::LOG_BITS;
+                            other
+                        })
+                        .or_else(|| {
+                            // Because there is only one bit left we descend to it
+                            let idx = level_prefix | first_bit as u64;
+                            self.0.prefix[level - 1] = idx << S::Underlying::LOG_BITS;
+                            // The level that is descended from doesn't have anything
+                            // interesting so it can be skipped in the future.
+                            self.0.masks[level] = S::Underlying::ZERO;
+                            self.0.masks[level - 1] =
+                                self.0.set.get_from_layer(level - 1, idx as usize);
+                            None
+                        })
+                }
+            };
+            let top_layer = L - 1;
+            let mut h = handle_level(top_layer);
+            for i in 1..splits {
+                h = h.or_else(|| handle_level(top_layer - i as usize));
+            }
+            h
+        };
+        (self, other)
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        folder.consume_iter(self.0)
+    }
+}
+
+impl<'a, S: 'a + WideBitSetLike<L>, const L: usize> WideBitProducer<'a, S, L> {
+    /// Splits off the back of the iterator, or the highest layer
+    /// of its front if more than one layer is left to iterate.
+    ///
+    /// Returns `None` if either half would be left empty.
+    fn split_off(&mut self) -> Option<Self> {
+        let zero = S::Underlying::ZERO;
+        if self.0.masks.iter().all(|&mask| mask == zero) {
+            // The front is exhausted, so it takes over the lowest bits of
+            // the back, as in `next`, to leave something on both sides.
+            if let Some(level) = (0..L).rev().find(|&level| self.0.back_masks[level] != zero) {
+                self.0.masks[level] = mem::replace(&mut self.0.back_masks[level], zero);
+                if level < L - 1 {
+                    self.0.prefix[level] = self.0.back_prefix[level];
+                }
+            }
+        }
+        let mut other = WideBitProducer(WideBitIter::new(self.0.set, [zero; L], [0; L]), self.1);
+        if self.0.back_masks.iter().any(|&mask| mask != zero) {
+            // The back only holds indices greater than the front
+            other.0.back_masks = self.0.back_masks;
+            other.0.back_prefix = self.0.back_prefix;
+            self.0.back_masks = [zero; L];
+            return Some(other);
+        }
+        let mut levels = (0..L).rev().filter(|&level| self.0.masks[level] != zero);
+        match (levels.next(), levels.next()) {
+            (Some(level), Some(_)) => {
+                other.0.masks[level] = self.0.masks[level];
+                other.0.prefix[level..].copy_from_slice(&self.0.prefix[level..]);
+                self.0.masks[level] = zero;
+                Some(other)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate typed_test_gen;
+    use self::typed_test_gen::test_with;
+    use rayon::iter::plumbing::UnindexedProducer;
+    use rayon::iter::ParallelIterator;
+
+    use super::WideBitProducer;
+    use {GenericWideBitSet, UnsignedInteger, WideBitSetLike};
+
+    #[test_with(u32, u64, usize, u128)]
+    fn par_iter<T: UnsignedInteger + Send + Sync>() {
+        let max = GenericWideBitSet::<T>::MAX_INDEX;
+        let step = max / 10_000;
+        let set: GenericWideBitSet<T> = (0..=max).step_by(step as usize).collect();
+        let mut collected = (&set).par_iter().collect::<Vec<_>>();
+        collected.sort();
+        assert_eq!(collected, (&set).iter().collect::<Vec<_>>());
+
+        let mut range = (&set)
+            .par_iter_range(step * 100..step * 200)
+            .collect::<Vec<_>>();
+        range.sort();
+        assert!(range.iter().cloned().eq((100..200).map(|i| i * step)));
+        let empty = GenericWideBitSet::<T>::new();
+        assert_eq!((&set | &empty).par_iter().count() as u64, set.len());
+    }
+
+    #[test_with(u32, u64, usize, u128)]
+    fn split_after_next_back<T: UnsignedInteger + Send + Sync>() {
+        let high = GenericWideBitSet::<T>::MAX_INDEX - 1_000;
+        let set: GenericWideBitSet<T> = (0..1_000).chain(high..high + 1_000).collect();
+        let mut iter = (&set).iter();
+        assert_eq!(iter.next_back(), Some(high + 999));
+        // The front is exhausted and only the back is left.
+        let (us, them) = WideBitProducer(iter, 3).split();
+        let them = them.expect("Splitting the back");
+        let (mut us, them): (Vec<_>, Vec<_>) = (us.0.collect(), them.0.collect());
+        assert!(!us.is_empty() && !them.is_empty());
+        us.extend(them);
+        us.sort();
+        assert!(us.iter().cloned().eq((0..1_000).chain(high..high + 999)));
+    }
+}